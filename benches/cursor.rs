@@ -0,0 +1,40 @@
+//! Allocation and reset on an `Arena`, to compare the atomic cursor and
+//! counters against the `Cell`-based ones of the `single-threaded` feature.
+//! Save a baseline with the atomics, then measure against it:
+//!
+//! ```text
+//! cargo bench --features system-allocator --bench cursor -- --save-baseline atomic
+//! cargo bench --features system-allocator,single-threaded --bench cursor -- --baseline atomic
+//! ```
+
+#[path = "../main.rs"]
+#[allow(dead_code, unused_imports)]
+mod bump;
+
+use bump::Arena;
+use core::alloc::Layout;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::hint::black_box;
+
+// Allocations per measured batch; the arena is reset between batches.
+const BATCH: usize = 1024;
+
+fn alloc_reset(c: &mut Criterion) {
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let arena = Arena::new(BATCH * layout.size());
+    let mut group = c.benchmark_group("cursor");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    group.bench_function("alloc_reset", |b| {
+        b.iter(|| {
+            for _ in 0..BATCH {
+                black_box(arena.try_alloc(layout).unwrap());
+            }
+            // Nothing handed out in the batch is used afterwards.
+            unsafe { arena.reset() };
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, alloc_reset);
+criterion_main!(benches);
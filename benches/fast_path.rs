@@ -0,0 +1,44 @@
+//! The word-aligned shortcut in the bump path against the general rounding
+//! it skips, for the small allocations `Box::new` makes most.
+//!
+//! Run with `cargo bench --features system-allocator --bench fast_path`.
+
+#[path = "../main.rs"]
+#[allow(dead_code, unused_imports)]
+mod bump;
+
+use bump::{checked_align_up, place_up, Arena};
+use core::alloc::Layout;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+
+// Allocations per measured batch; the arena is reset between batches.
+const BATCH: usize = 1024;
+
+fn bump_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bump_path");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    for size in [8, 16, 24] {
+        let layout = Layout::from_size_align(size, 8).unwrap();
+        let arena = Arena::new(BATCH * size);
+        let run = |place: fn(usize, usize) -> Option<usize>| {
+            let (start, end) = (arena.heap_start(), arena.heap_end());
+            for _ in 0..BATCH {
+                let block = arena.bump_placed(layout, start, end, |at| place(at, layout.align()));
+                black_box(block.unwrap());
+            }
+            // Nothing handed out in the batch is used afterwards.
+            unsafe { arena.reset() };
+        };
+        group.bench_with_input(BenchmarkId::new("fast", size), &size, |b, _| {
+            b.iter(|| run(place_up))
+        });
+        group.bench_with_input(BenchmarkId::new("general", size), &size, |b, _| {
+            b.iter(|| run(checked_align_up::<usize>))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bump_path);
+criterion_main!(benches);
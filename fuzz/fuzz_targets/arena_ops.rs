@@ -0,0 +1,133 @@
+//! Random sequences of allocations, frees, marks, rewinds and resets against
+//! a standalone `Arena`, checking the bookkeeping after every step:
+//!
+//!     cargo +nightly fuzz run arena_ops
+//!
+//! The invariants are the ones `check_invariants` spells out (cursor inside
+//! the heap, no overlapping live allocations, used plus remaining equal to
+//! the capacity), plus that no live block's contents get overwritten.
+
+#![no_main]
+
+use core::alloc::{GlobalAlloc, Layout};
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../main.rs"]
+#[allow(dead_code)]
+mod bump;
+
+use bump::{AllocInfo, Arena, Marker};
+
+const CAPACITY: usize = 1 << 14;
+// Bounds how many blocks can be live at once, well within the tracker.
+const MAX_OPS: usize = 2048;
+
+#[derive(Arbitrary, Debug)]
+enum FreeList {
+    Off,
+    Inline,
+    OutOfBand,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Alloc { size: u8, align_log2: u8 },
+    Free { index: u16 },
+    Mark,
+    Rewind,
+    Reset,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    free_list: FreeList,
+    ops: Vec<Op>,
+}
+
+// A live block, and the byte it was filled with.
+struct Block {
+    ptr: *mut u8,
+    layout: Layout,
+    fill: u8,
+}
+
+impl Block {
+    fn info(&self, arena: &Arena) -> AllocInfo {
+        AllocInfo {
+            offset: self.ptr as usize - arena.heap_range().0,
+            size: self.layout.size(),
+        }
+    }
+
+    fn intact(&self) -> bool {
+        let bytes = unsafe { core::slice::from_raw_parts(self.ptr, self.layout.size()) };
+        bytes.iter().all(|&b| b == self.fill)
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let mut arena = Arena::new(CAPACITY);
+    arena.enable_tracking(MAX_OPS);
+    match input.free_list {
+        FreeList::Off => {}
+        FreeList::Inline => arena.enable_free_list(),
+        FreeList::OutOfBand => arena.enable_free_list_out_of_band(64),
+    }
+
+    let mut live: Vec<Block> = Vec::new();
+    let mut marks: Vec<Marker> = Vec::new();
+    for (step, op) in input.ops.iter().take(MAX_OPS).enumerate() {
+        match *op {
+            Op::Alloc { size, align_log2 } => {
+                let layout = Layout::from_size_align(size as usize, 1 << (align_log2 % 7)).unwrap();
+                if let Ok(ptr) = arena.try_alloc(layout) {
+                    let fill = step as u8;
+                    unsafe { core::ptr::write_bytes(ptr.as_ptr(), fill, layout.size()) };
+                    live.push(Block { ptr: ptr.as_ptr(), layout, fill });
+                }
+            }
+            Op::Free { index } => {
+                if !live.is_empty() {
+                    let block = live.swap_remove(index as usize % live.len());
+                    assert!(block.intact(), "block overwritten while live");
+                    unsafe { arena.dealloc(block.ptr, block.layout) };
+                }
+            }
+            Op::Mark => marks.push(arena.mark()),
+            Op::Rewind => {
+                if let Some(mark) = marks.pop() {
+                    unsafe { arena.rewind(mark) };
+                    // Whatever the rewind reclaimed is no longer tracked.
+                    let tracked = arena.allocations();
+                    live.retain(|block| tracked.contains(&block.info(&arena)));
+                }
+            }
+            Op::Reset => {
+                unsafe { arena.reset() };
+                live.clear();
+                marks.clear();
+            }
+        }
+
+        if let Err(broken) = arena.check_invariants() {
+            panic!("after {op:?}: {broken}");
+        }
+        assert_eq!(arena.bytes_used() + arena.bytes_remaining(), arena.capacity());
+        let (start, end) = arena.heap_range();
+        // Zero-sized blocks own no bytes, so they can't overlap anything.
+        let mut spans: Vec<(usize, usize)> = live
+            .iter()
+            .filter(|block| block.layout.size() != 0)
+            .map(|block| (block.ptr as usize, block.ptr as usize + block.layout.size()))
+            .collect();
+        spans.sort_unstable();
+        for &(lo, hi) in &spans {
+            assert!(start <= lo && hi <= end, "live block outside the heap");
+        }
+        for pair in spans.windows(2) {
+            assert!(pair[0].1 <= pair[1].0, "live blocks overlap");
+        }
+        assert!(live.iter().all(Block::intact), "block overwritten while live");
+    }
+});
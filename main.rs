@@ -1,12 +1,45 @@
 use core::alloc::{GlobalAlloc, Layout};
-use core::ptr::null_mut;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use core::cell::{Cell, RefCell, UnsafeCell};
+use core::fmt;
+use core::ptr::{null_mut, NonNull};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, Once};
+#[cfg(all(target_os = "linux", not(feature = "single-threaded")))]
+use std::sync::atomic::AtomicU32;
 
 // 1. We define a fixed-size buffer that will act as our "heap."
 //    For real-world use, you'd want something more flexible or dynamic.
 const HEAP_SIZE: usize = 1024 * 1024; // 1 MiB for demo
+#[cfg(not(feature = "single-threaded"))]
 static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
 
+const WORD_SIZE: usize = core::mem::size_of::<usize>();
+
+// A zero-sized heap would build fine and then fail every allocation, and one
+// that isn't a whole number of words wastes its tail, so catch both at compile
+// time. (A function so the compile-fail test can feed it a bad size.)
+const _: () = check_heap_size(HEAP_SIZE);
+
+pub(crate) const fn check_heap_size(size: usize) {
+    assert!(size > 0, "HEAP_SIZE must be nonzero");
+    assert!(
+        size.is_multiple_of(WORD_SIZE),
+        "HEAP_SIZE must be a multiple of the word size"
+    );
+}
+
+// What reclaimed memory is filled with in poison mode.
+const POISON_BYTE: u8 = 0xA5;
+
+// One size-histogram bucket per power of two an allocation size can have.
+const HISTOGRAM_BUCKETS: usize = usize::BITS as usize;
+
+thread_local! {
+    // Allocations left to skip before the next sampled one, when stats
+    // sampling is on. Shared by every allocator the thread uses.
+    static SAMPLE_COUNTDOWN: Cell<usize> = const { Cell::new(0) };
+}
+
 // A simple helper function to align the current offset
 // to the alignment required by `layout.align()`.
 #[inline]
@@ -15,122 +48,6881 @@ fn align_up(addr: usize, align: usize) -> usize {
     (addr + align - 1) & !(align - 1)
 }
 
+/// The unsigned integer types `checked_align_up` and `align_down` work on,
+/// for offset math in widths other than `usize` (say, `u32` offsets in a
+/// file format).
+pub trait Unsigned:
+    Copy
+    + Eq
+    + core::ops::BitAnd<Output = Self>
+    + core::ops::Not<Output = Self>
+    + core::ops::Sub<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+}
+
+macro_rules! impl_unsigned {
+    ($($t:ty),*) => {$(
+        impl Unsigned for $t {
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+
+            fn checked_add(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_add(self, rhs)
+            }
+        }
+    )*};
+}
+
+impl_unsigned!(u8, u16, u32, u64, u128, usize);
+
+/// Round `value` up to a multiple of `align`, or `None` if that overflows
+/// or `align` isn't a power of two.
+pub fn checked_align_up<T: Unsigned>(value: T, align: T) -> Option<T> {
+    let mask = power_of_two_mask(align)?;
+    Some(value.checked_add(mask)? & !mask)
+}
+
+// Where an upward bump puts a block whose lowest possible start is `start`.
+// Most requests (`Box::new` of small types and the like) are at most word
+// aligned and the cursor usually already is, so skip the general rounding in
+// that case; `benches/fast_path.rs` measures what that saves.
+#[inline]
+pub(crate) fn place_up(start: usize, align: usize) -> Option<usize> {
+    if align <= WORD_SIZE && start & (WORD_SIZE - 1) == 0 {
+        Some(start)
+    } else {
+        checked_align_up(start, align)
+    }
+}
+
+/// Round `value` down to a multiple of `align`, or `None` if `align` isn't
+/// a power of two.
+pub fn align_down<T: Unsigned>(value: T, align: T) -> Option<T> {
+    Some(value & !power_of_two_mask(align)?)
+}
+
+// `align - 1`, if `align` is a power of two.
+fn power_of_two_mask<T: Unsigned>(align: T) -> Option<T> {
+    if align == T::ZERO || align & (align - T::ONE) != T::ZERO {
+        return None;
+    }
+    Some(align - T::ONE)
+}
+
+/// Why an allocation failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocError {
+    /// Not enough room left in the heap.
+    OutOfMemory,
+    /// The requested alignment is larger than the whole heap.
+    InvalidAlign,
+    /// A panic unwound through an earlier allocation (see
+    /// `BumpAllocator::is_poisoned`).
+    Poisoned,
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AllocError::OutOfMemory => f.write_str("out of memory"),
+            AllocError::InvalidAlign => f.write_str("alignment larger than the heap"),
+            AllocError::Poisoned => f.write_str("allocator poisoned by an earlier panic"),
+        }
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+/// Why `checked_layout` couldn't build a `Layout`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutBuildError {
+    /// The alignment is zero or not a power of two.
+    AlignNotPowerOfTwo,
+    /// The size, rounded up to the alignment, is bigger than `isize::MAX`.
+    SizeOverflow,
+}
+
+impl fmt::Display for LayoutBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutBuildError::AlignNotPowerOfTwo => {
+                f.write_str("alignment must be a non-zero power of two")
+            }
+            LayoutBuildError::SizeOverflow => {
+                f.write_str("size rounded up to the alignment overflows isize")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LayoutBuildError {}
+
+/// `Layout::from_size_align`, but saying what was wrong with the input.
+pub fn checked_layout(size: usize, align: usize) -> Result<Layout, LayoutBuildError> {
+    if !align.is_power_of_two() {
+        return Err(LayoutBuildError::AlignNotPowerOfTwo);
+    }
+    Layout::from_size_align(size, align).map_err(|_| LayoutBuildError::SizeOverflow)
+}
+
+/// Which way a `BumpAllocator` fills its heap; see `with_direction`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// From the start of the heap towards the end, so later allocations get
+    /// higher addresses. The default.
+    Up,
+    /// From the end of the heap towards the start, so later allocations get
+    /// lower addresses, the way a stack grows. Only plain bumping goes this
+    /// way: the free list, markers, in-place growth and
+    /// `alloc_with_offset_align` are for upward heaps, so a downward one
+    /// writes off every free but the most recent block, ignores markers,
+    /// always moves on `realloc`, and returns null from
+    /// `alloc_with_offset_align`.
+    Down,
+}
+
+/// A saved cursor position; see `BumpAllocator::mark` and `rewind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Marker {
+    position: usize,
+    wasted_padding: usize,
+    dead_bytes: usize,
+    generation: usize,
+}
+
+/// A `Marker` plus the statistics at the time; see
+/// `BumpAllocator::checkpoint_full`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FullCheckpoint {
+    marker: Marker,
+    alloc_count: usize,
+    fast_allocs: usize,
+    freelist_allocs: usize,
+    peak: usize,
+    size_histogram: [usize; HISTOGRAM_BUCKETS],
+    alignment_histogram: [usize; HISTOGRAM_BUCKETS],
+}
+
+/// A snapshot of an allocator's usage figures; see `BumpAllocator::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BumpStats {
+    pub bytes_used: usize,
+    pub peak: usize,
+    pub alloc_count: usize,
+    /// Allocations bumped straight from the cursor.
+    pub fast_allocs: usize,
+    /// Allocations that reused a block from the free list.
+    pub freelist_allocs: usize,
+    /// Allocations that didn't fit where they were first tried and went
+    /// somewhere else (a later chunk of a `ChunkedArena`).
+    pub fallback_allocs: usize,
+    /// Times more memory was added (chunks, for a `ChunkedArena`).
+    pub grow_events: usize,
+}
+
+impl core::ops::AddAssign for BumpStats {
+    fn add_assign(&mut self, other: BumpStats) {
+        self.bytes_used += other.bytes_used;
+        self.peak += other.peak;
+        self.alloc_count += other.alloc_count;
+        self.fast_allocs += other.fast_allocs;
+        self.freelist_allocs += other.freelist_allocs;
+        self.fallback_allocs += other.fallback_allocs;
+        self.grow_events += other.grow_events;
+    }
+}
+
+/// What an out-of-memory handler (see `BumpAllocator::set_oom_handler`) is
+/// told about a failed allocation.
+#[derive(Clone, Copy, Debug)]
+pub struct OomReport {
+    pub name: Option<&'static str>,
+    pub layout: Layout,
+    pub error: AllocError,
+    pub bytes_remaining: usize,
+    pub capacity: usize,
+}
+
+impl OomReport {
+    /// Whether the request was bigger than the whole heap, i.e. could never
+    /// have succeeded, as opposed to the heap having filled up.
+    #[cfg(feature = "warn-on-large")]
+    pub fn exceeds_heap(&self) -> bool {
+        self.layout.size() > self.capacity
+    }
+}
+
+impl fmt::Display for OomReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = self.name {
+            write!(f, "{}: ", name)?;
+        }
+        #[cfg(feature = "warn-on-large")]
+        if self.exceeds_heap() {
+            return write!(
+                f,
+                "request exceeds total heap: {} bytes asked for, heap is only {} bytes",
+                self.layout.size(),
+                self.capacity
+            );
+        }
+        write!(
+            f,
+            "failed to allocate {} bytes (align {}): {}, {} of {} bytes left",
+            self.layout.size(),
+            self.layout.align(),
+            self.error,
+            self.bytes_remaining,
+            self.capacity
+        )
+    }
+}
+
+// The allocator's cursor and counters. Normally atomics, so one allocator can
+// be shared between threads (and be the global allocator). With the
+// `single-threaded` feature they're plain `Cell`s instead, which makes
+// `BumpAllocator` `!Sync` but takes every atomic read-modify-write off the
+// allocation path, for standalone arenas that stay on one thread.
+#[cfg(not(feature = "single-threaded"))]
+type Counter = AtomicUsize;
+#[cfg(feature = "single-threaded")]
+type Counter = LocalCounter;
+
+// `Cell<usize>` with the subset of the `AtomicUsize` API the allocator uses,
+// so the two are interchangeable. Orderings are meaningless on one thread
+// and ignored.
+#[cfg(feature = "single-threaded")]
+struct LocalCounter(Cell<usize>);
+
+#[cfg(feature = "single-threaded")]
+impl LocalCounter {
+    const fn new(value: usize) -> Self {
+        LocalCounter(Cell::new(value))
+    }
+
+    #[inline]
+    fn load(&self, _: Ordering) -> usize {
+        self.0.get()
+    }
+
+    #[inline]
+    fn store(&self, value: usize, _: Ordering) {
+        self.0.set(value);
+    }
+
+    #[inline]
+    fn swap(&self, value: usize, _: Ordering) -> usize {
+        self.0.replace(value)
+    }
+
+    #[inline]
+    fn fetch_add(&self, value: usize, _: Ordering) -> usize {
+        self.0.replace(self.0.get() + value)
+    }
+
+    #[inline]
+    fn fetch_sub(&self, value: usize, _: Ordering) -> usize {
+        self.0.replace(self.0.get() - value)
+    }
+
+    #[inline]
+    fn fetch_max(&self, value: usize, _: Ordering) -> usize {
+        self.0.replace(self.0.get().max(value))
+    }
+
+    #[inline]
+    fn fetch_min(&self, value: usize, _: Ordering) -> usize {
+        self.0.replace(self.0.get().min(value))
+    }
+
+    #[inline]
+    fn compare_exchange(
+        &self,
+        current: usize,
+        new: usize,
+        _: Ordering,
+        _: Ordering,
+    ) -> Result<usize, usize> {
+        let actual = self.0.get();
+        if actual == current {
+            self.0.set(new);
+            Ok(actual)
+        } else {
+            Err(actual)
+        }
+    }
+
+    #[inline]
+    fn compare_exchange_weak(
+        &self,
+        current: usize,
+        new: usize,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<usize, usize> {
+        self.compare_exchange(current, new, success, failure)
+    }
+}
+
 // 2. A simple bump allocator structure.
 pub struct BumpAllocator {
     // The starting address of the heap (as a usize).
-    heap_start: usize,
+    heap_start: Counter,
     // The ending address of the heap (as a usize).
-    heap_end: usize,
+    heap_end: Counter,
+    // The end of the region the heap was given; `heap_end` is only ever
+    // below it after `set_usable_end`.
+    region_end: Counter,
     // An atomic to hold the *next* allocation index.
-    // Using `AtomicUsize` allows us to do lock-free increments,
-    // though we are ignoring concurrency issues for this example.
-    next: AtomicUsize,
+    // Using `AtomicUsize` allows us to do lock-free increments.
+    next: Counter,
+    // Bytes skipped over by `align_up` to satisfy alignment requests.
+    wasted_padding: Counter,
+    // Bytes of freed blocks that couldn't be handed back to the cursor
+    // because something else was allocated after them, and haven't been
+    // reused from the free list since.
+    dead_bytes: Counter,
+    // Highest address the cursor has ever reached (lowest, if it grows
+    // down). Everything beyond it has never been handed out, so is still
+    // zero.
+    high_water: Counter,
+    // Largest `bytes_used()` seen so far.
+    peak: Counter,
+    // Number of successful allocations.
+    alloc_count: Counter,
+    // Number of failed ones (not counting those refused for poisoning).
+    oom_count: Counter,
+    // How many of the successful ones reused a free-list block. Counted
+    // exactly, since that path takes a lock anyway.
+    freelist_allocs: Counter,
+    // How many were bumped from the cursor, sampled like `alloc_count`.
+    fast_allocs: Counter,
+    // Successful allocations by size: bucket `i` counts sizes in
+    // `[2^i, 2^(i+1))`, with zero-sized ones in bucket 0.
+    size_histogram: [Counter; HISTOGRAM_BUCKETS],
+    // Successful allocations by requested alignment: bucket `i` counts
+    // alignment `2^i`.
+    align_histogram: [Counter; HISTOGRAM_BUCKETS],
+    // Only record every Nth allocation in `alloc_count` and the histograms,
+    // weighted by N. 1 means record everything.
+    sample_every: Counter,
+    // `pressure()` at or above which `is_under_pressure()` says yes, as
+    // `f32` bits.
+    pressure_threshold: Counter,
+    // Whether memory handed back by `free_above` gets overwritten with
+    // `POISON_BYTE`, so use of a stale pointer reads obvious garbage.
+    poison: AtomicBool,
+    // Bumped by every `reset`, so `Handle`s can tell they've gone stale.
+    generation: Counter,
+    // Which way `next` moves; fixed before the heap is set up.
+    direction: Direction,
+    // Shown in diagnostics to tell allocators apart.
+    name: Option<&'static str>,
+    // `fn(&OomReport)` to call when an allocation fails, as a usize so it
+    // can live in an atomic; 0 means none.
+    oom_handler: Counter,
+    // Supplies the heap on first use, for allocators created without one,
+    // and whether it comes zero-filled.
+    lazy_region: Option<fn() -> &'static mut [u8]>,
+    lazy_zeroed: bool,
+    lazy_init: Once,
+    // Smaller allocations are rounded up to this many bytes; 0 means no
+    // minimum.
+    min_block_size: Counter,
+    // Whether freed non-top blocks go on `free_list` for reuse instead of
+    // just being written off as dead.
+    reuse_freed: AtomicBool,
+    free_list: Mutex<FreeList>,
+    // Bytes currently sitting on `free_list`, readable without the lock.
+    free_listed: Counter,
+    // Link in the `StatsRegistry` this allocator was added to, if any.
+    registered: AtomicBool,
+    registry_next: AtomicPtr<BumpAllocator>,
+    // Set if a panic unwound through an allocation (say, from the OOM
+    // handler), which may have left the bookkeeping half-updated.
+    poisoned: AtomicBool,
+    // Allocations currently in progress, so debug builds can catch a `reset`
+    // racing one.
+    #[cfg(debug_assertions)]
+    in_flight: Counter,
+    // Whether live allocations are being recorded in `tracker`.
+    tracking: AtomicBool,
+    tracker: Mutex<Tracker>,
+    // Allocation events waiting for `drain_events`.
+    #[cfg(feature = "profile-ring")]
+    events: EventRing,
+    // Every allocator operation since `enable_op_log`, for `op_log`; null
+    // until then, so an allocator that isn't recording doesn't carry the
+    // log around.
+    #[cfg(feature = "record-ops")]
+    ops: AtomicPtr<OpLog>,
 }
 
-unsafe impl Sync for BumpAllocator {} // Required for global allocator, trivial here
+impl BumpAllocator {
+    /// An allocator handing out memory from `[start, start + size)`. The caller
+    /// is responsible for that region staying valid while the allocator is used.
+    const fn for_region(start: usize, size: usize) -> Self {
+        BumpAllocator {
+            heap_start: Counter::new(start),
+            heap_end: Counter::new(start + size),
+            region_end: Counter::new(start + size),
+            next: Counter::new(start),
+            wasted_padding: Counter::new(0),
+            dead_bytes: Counter::new(0),
+            high_water: Counter::new(start),
+            peak: Counter::new(0),
+            alloc_count: Counter::new(0),
+            oom_count: Counter::new(0),
+            freelist_allocs: Counter::new(0),
+            fast_allocs: Counter::new(0),
+            size_histogram: [const { Counter::new(0) }; HISTOGRAM_BUCKETS],
+            align_histogram: [const { Counter::new(0) }; HISTOGRAM_BUCKETS],
+            sample_every: Counter::new(1),
+            pressure_threshold: Counter::new(1.0f32.to_bits() as usize),
+            poison: AtomicBool::new(false),
+            generation: Counter::new(0),
+            direction: Direction::Up,
+            name: None,
+            oom_handler: Counter::new(0),
+            lazy_region: None,
+            lazy_zeroed: false,
+            lazy_init: Once::new(),
+            min_block_size: Counter::new(0),
+            reuse_freed: AtomicBool::new(false),
+            free_list: Mutex::new(FreeList::new()),
+            free_listed: Counter::new(0),
+            registered: AtomicBool::new(false),
+            registry_next: AtomicPtr::new(null_mut()),
+            poisoned: AtomicBool::new(false),
+            #[cfg(debug_assertions)]
+            in_flight: Counter::new(0),
+            tracking: AtomicBool::new(false),
+            tracker: Mutex::new(Tracker::new()),
+            #[cfg(feature = "profile-ring")]
+            events: EventRing::new(),
+            #[cfg(feature = "record-ops")]
+            ops: AtomicPtr::new(null_mut()),
+        }
+    }
 
-// 3. Implement `GlobalAlloc` for our `BumpAllocator`.
-unsafe impl GlobalAlloc for BumpAllocator {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let align = layout.align();
-        let size = layout.size();
+    /// An allocator with no heap yet, for use in a `static`: every allocation
+    /// fails until `init` gives it a region.
+    pub const fn new_uninitialized() -> Self {
+        Self::for_region(0, 0)
+    }
+
+    /// An allocator that gets its heap by calling `region` the first time
+    /// something is allocated, so nothing is reserved (or mapped) for an
+    /// allocator that never gets used. `region` runs exactly once, and must
+    /// not itself allocate from this allocator.
+    pub const fn with_lazy_region(region: fn() -> &'static mut [u8]) -> Self {
+        let mut alloc = Self::for_region(0, 0);
+        alloc.lazy_region = Some(region);
+        alloc
+    }
 
-        // current allocation pointer
-        let mut current_next = self.next.load(Ordering::Relaxed);
+    /// `with_lazy_region`, for a region that's known to start out zeroed
+    /// (say, a `static` array), which saves `alloc_zeroed` clearing blocks
+    /// that have never been handed out.
+    ///
+    /// # Safety
+    ///
+    /// Every byte of the slice `region` returns must be zero.
+    pub const unsafe fn with_lazy_zeroed_region(region: fn() -> &'static mut [u8]) -> Self {
+        let mut alloc = Self::with_lazy_region(region);
+        alloc.lazy_zeroed = true;
+        alloc
+    }
 
-        // Bump the pointer up to meet alignment requirements
-        let aligned = align_up(current_next, align);
-        let new_next = aligned.saturating_add(size);
+    /// Make the heap fill up in `direction` instead of upwards, e.g.
+    /// `with_lazy_region(region).with_direction(Direction::Down)` for a
+    /// global allocator whose addresses decrease over time. Only meant for
+    /// allocators whose heap isn't set up yet, which is all the public
+    /// constructors give.
+    pub const fn with_direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
 
-        // Check for out-of-memory
-        if new_next > self.heap_end {
-            // Not enough space
-            return null_mut();
+    /// Hand an uninitialized allocator its heap, `[start, start + size)`.
+    /// The region may hold anything; `alloc_zeroed` clears what it hands out.
+    ///
+    /// # Safety
+    ///
+    /// The region must be valid for reads and writes, not used by anything
+    /// else, and outlive the allocator. Nothing may have been allocated yet.
+    pub unsafe fn init(&self, start: usize, size: usize) {
+        self.heap_start.store(start, Ordering::SeqCst);
+        self.heap_end.store(start + size, Ordering::SeqCst);
+        self.region_end.store(start + size, Ordering::SeqCst);
+        // Nothing is known about what the region holds, so count every byte
+        // of it as handed out before, i.e. as needing clearing.
+        let far_end = match self.direction {
+            Direction::Up => self.heap_end(),
+            Direction::Down => self.heap_start(),
+        };
+        self.high_water.store(far_end, Ordering::SeqCst);
+        self.next.store(self.origin(), Ordering::SeqCst);
+    }
+
+    // Where the cursor sits on an empty heap: the end it grows away from.
+    #[inline]
+    fn origin(&self) -> usize {
+        match self.direction {
+            Direction::Up => self.heap_start(),
+            Direction::Down => self.heap_end(),
         }
+    }
 
-        // CAS loop if multiple threads might attempt allocations at once
-        // For simplicity, do a single store here ignoring concurrency complexities
-        self.next.store(new_next, Ordering::Relaxed);
+    // Bytes between the cursor and the end of the heap it grows towards.
+    #[inline]
+    fn cursor_room(&self, ordering: Ordering) -> usize {
+        let next = self.next.load(ordering);
+        match self.direction {
+            Direction::Up => self.heap_end() - next,
+            Direction::Down => next - self.heap_start(),
+        }
+    }
 
-        aligned as *mut u8
+    // The heap bounds only change in `init`, which has to happen before any
+    // allocation, and `set_usable_end`, which mustn't race one, so relaxed
+    // loads are enough.
+    #[inline]
+    pub(crate) fn heap_start(&self) -> usize {
+        self.heap_start.load(Ordering::Relaxed)
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        // In a naive bump allocator, deallocation is a no-op or near no-op.
-        // Proper free/defragmentation is not handled here. 
+    #[inline]
+    pub(crate) fn heap_end(&self) -> usize {
+        self.heap_end.load(Ordering::Relaxed)
     }
-}
 
-// 4. Create a static instance of our BumpAllocator and tag it as the global allocator.
-#[global_allocator]
-static GLOBAL: BumpAllocator = BumpAllocator {
-    heap_start: 0,
-    heap_end: 0,
-    next: AtomicUsize::new(0),
-};
-
-// 5. We use Rust's `#[ctor]`-like approach or a manual "init" function to properly
-// initialize the heap addresses *before main* runs. In stable Rust, the easiest
-// approach is to do it in `main` the first time we need it. We'll do a function here
-// that MUST be called before any real allocations. This is a simplified approach.
-fn init_heap() {
-    unsafe {
-        let start = HEAP.as_ptr() as usize;
-        let end = start + HEAP_SIZE;
+    /// The heap's `(start, end)` addresses, for turning pointers from this
+    /// allocator into offsets. Both are 0 until the heap is set up.
+    pub fn heap_range(&self) -> (usize, usize) {
+        (
+            self.heap_start.load(Ordering::Acquire),
+            self.heap_end.load(Ordering::Acquire),
+        )
+    }
 
-        GLOBAL.next.store(start, Ordering::SeqCst);
-        let bump_alloc = &GLOBAL as *const BumpAllocator as *mut BumpAllocator;
-        (*bump_alloc).heap_start = start;
-        (*bump_alloc).heap_end = end;
+    /// Label this allocator in OOM reports, leak reports and `Debug` output.
+    pub fn set_name(&mut self, name: &'static str) {
+        self.name = Some(name);
     }
-}
 
-fn main() {
-    // Initialize the bump allocator
-    init_heap();
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
 
-    // **DEMO A**: Allocate a Box on our custom "heap"
-    // The memory used by this Box will come from our BumpAllocator, not the default system malloc.
-    let my_box = Box::new(42);
-    println!("Allocated a box with value: {}", my_box);
+    /// Total size of the heap; zero before `init`.
+    pub fn capacity(&self) -> usize {
+        self.heap_end() - self.heap_start()
+    }
 
-    // **DEMO B**: Allocate a vector
-    // This will also use our custom allocator.
-    let mut my_vec = Vec::with_capacity(10);
-    my_vec.extend_from_slice(&[1, 2, 3, 4, 5]);
-    println!("Allocated a vector: {:?}", my_vec);
+    /// How many bytes a freshly reset heap can hand out. The allocator keeps
+    /// no header inside its heap, so this is just `capacity`; it's here so
+    /// code can size workloads the same way for an `MmapArena`, whose
+    /// capacity counts the header at the start of its mapping.
+    pub fn usable_capacity(&self) -> usize {
+        self.capacity()
+    }
 
-    // **DEMO C**: Manual "malloc" style usage with pointer arithmetic
-    // We'll do a small example to store some data using unsafe pointers.
-    unsafe {
-        let size = 8; // let's say we want 8 bytes
-        let align = 4; // alignment requirement
-        let layout = Layout::from_size_align(size, align).unwrap();
+    /// Move the end of the heap to `end`, e.g. so an OOM test can run out
+    /// after a few small allocations instead of filling the whole region.
+    /// It's clamped to lie between the cursor and the end of the region the
+    /// heap was given, so it can be raised again later. `capacity` and
+    /// everything derived from it follow the new end. A testing and tuning
+    /// hook; a downward heap, whose cursor starts at the end, ignores it.
+    ///
+    /// # Safety
+    ///
+    /// No other thread may be allocating while the end moves.
+    pub unsafe fn set_usable_end(&self, end: usize) {
+        if self.direction == Direction::Down {
+            return;
+        }
+        let end = end
+            .max(self.next.load(Ordering::Acquire))
+            .min(self.region_end.load(Ordering::Acquire));
+        self.heap_end.store(end, Ordering::Release);
+    }
 
-        // Our naive 'malloc' call
-        let ptr = GLOBAL.alloc(layout);
-        if !ptr.is_null() {
-            // Store data: We'll store two 32-bit integers in that block
-            // `ptr` is of type *mut u8; let's cast it to a *mut u32 for storing an integer
-            let int_ptr = ptr as *mut u32;
-            *int_ptr = 0xDEAD_BEEF;      // store first integer
-            *int_ptr.add(1) = 0xC0FFEE;  // store second integer
+    /// Whether nothing is currently allocated.
+    pub fn is_empty(&self) -> bool {
+        self.bytes_used() == 0
+    }
 
-            // Read back the data
-            println!(
-                "Manually allocated block. First: 0x{:X}, Second: 0x{:X}",
-                *int_ptr,
-                *int_ptr.add(1)
-            );
+    /// Bytes between the start of the heap and the cursor, minus any sitting
+    /// on the free list.
+    pub fn bytes_used(&self) -> usize {
+        self.bytes_used_with(Ordering::Acquire)
+    }
+
+    /// `bytes_used` with a caller-chosen load ordering: `Relaxed` for cheap
+    /// sampling, `SeqCst` for strict reads. Like any atomic load, `Release`
+    /// and `AcqRel` panic.
+    pub fn bytes_used_with(&self, ordering: Ordering) -> usize {
+        // The cursor and the listed bytes are read one after the other, so a
+        // free racing the two reads can list bytes the cursor read has
+        // already been moved back over; saturate rather than underflow.
+        (self.capacity() - self.cursor_room(ordering))
+            .saturating_sub(self.free_listed.load(ordering))
+    }
+
+    /// Bytes between the cursor and the end of the heap, plus any sitting on
+    /// the free list. Fragmentation means not all of it may be usable by a
+    /// single allocation.
+    pub fn bytes_remaining(&self) -> usize {
+        // Clamped for the same race as in `bytes_used_with`.
+        (self.cursor_room(Ordering::Acquire) + self.free_listed.load(Ordering::Acquire))
+            .min(self.capacity())
+    }
+
+    /// Check whether `bytes` more would fit, `Vec::try_reserve` style:
+    /// `Ok(remaining after the reservation)` if they do, `Err(available)` if
+    /// they don't. Nothing is allocated.
+    pub fn try_reserve(&self, bytes: usize) -> Result<usize, usize> {
+        let available = self.bytes_remaining();
+        available.checked_sub(bytes).ok_or(available)
+    }
+
+    /// How full the heap is, from 0.0 (empty) to 1.0 (full), for producers to
+    /// back off before allocations start failing. A heap with no capacity
+    /// counts as full.
+    pub fn pressure(&self) -> f32 {
+        match self.capacity() {
+            0 => 1.0,
+            capacity => self.bytes_used() as f32 / capacity as f32,
+        }
+    }
+
+    /// Set the `pressure()` at which `is_under_pressure()` starts saying yes.
+    /// Until this is called, only a full heap counts.
+    pub fn set_pressure_threshold(&self, threshold: f32) {
+        self.pressure_threshold
+            .store(threshold.to_bits() as usize, Ordering::Relaxed);
+    }
+
+    pub fn is_under_pressure(&self) -> bool {
+        let threshold = f32::from_bits(self.pressure_threshold.load(Ordering::Relaxed) as u32);
+        self.pressure() >= threshold
+    }
+
+    /// Whether an allocation of exactly `layout` would succeed right now,
+    /// using the same alignment and bounds math as `alloc`, but without
+    /// allocating. Unlike `bytes_remaining`, this accounts for padding.
+    pub fn can_fit(&self, layout: Layout) -> bool {
+        let Ok(layout) = self.padded(layout) else {
+            return false;
+        };
+        if self.check_layout(layout).is_err() {
+            return false;
+        }
+        if self.free_listed.load(Ordering::Acquire) != 0 && self.lock_free_list().fits(layout) {
+            return true;
+        }
+        let next = self.next.load(Ordering::Acquire);
+        match self.direction {
+            Direction::Up => checked_align_up(next, layout.align())
+                .and_then(|aligned| aligned.checked_add(layout.size()))
+                .is_some_and(|end| end <= self.heap_end()),
+            Direction::Down => next
+                .checked_sub(layout.size())
+                .is_some_and(|lowest| lowest & !(layout.align() - 1) >= self.heap_start()),
+        }
+    }
+
+    /// How many padding bytes the next bump of a block aligned to `align` (and
+    /// sized a multiple of it) would skip, as things stand; nothing is
+    /// allocated. For checking hand-packed layouts. Panics if `align` isn't a
+    /// power of two.
+    pub fn padding_for(&self, align: usize) -> usize {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        let next = self.next.load(Ordering::Acquire);
+        match self.direction {
+            // Aligning up can only overflow right at the top of the address
+            // space, where nothing aligned fits at all.
+            Direction::Up => {
+                checked_align_up(next, align).map_or(usize::MAX, |aligned| aligned - next)
+            }
+            Direction::Down => next & (align - 1),
+        }
+    }
+
+    /// The biggest contiguous free region: the larger of the untouched tail
+    /// and the biggest free-list block. Unlike `bytes_remaining`, this is an
+    /// honest bound on the next allocation when the heap is fragmented
+    /// (alignment padding aside).
+    pub fn largest_free_block(&self) -> usize {
+        let mut largest = self.cursor_room(Ordering::Acquire);
+        if self.free_listed.load(Ordering::Acquire) != 0 {
+            self.lock_free_list()
+                .for_each(|_, size| largest = largest.max(size));
+        }
+        largest
+    }
+
+    /// How many more `T`s could be bump-allocated (as one array, or one by
+    /// one) from the cursor, after aligning it for the first. Free-list
+    /// blocks aren't counted.
+    pub fn capacity_for<T>(&self) -> usize {
+        let layout = Layout::new::<T>();
+        let next = self.next.load(Ordering::Acquire);
+        let room = match self.direction {
+            Direction::Up => checked_align_up(next, layout.align())
+                .and_then(|start| self.heap_end().checked_sub(start)),
+            Direction::Down => (next & !(layout.align() - 1)).checked_sub(self.heap_start()),
+        };
+        match (room, layout.size()) {
+            (None, _) => 0,
+            (Some(_), 0) => usize::MAX,
+            (Some(room), size) => room / size,
+        }
+    }
+
+    /// The most bytes that have been in use at once.
+    pub fn peak(&self) -> usize {
+        self.peak_with(Ordering::Acquire)
+    }
+
+    /// `peak` with a caller-chosen load ordering (see `bytes_used_with`).
+    pub fn peak_with(&self, ordering: Ordering) -> usize {
+        self.peak.load(ordering)
+    }
+
+    /// How many allocations have succeeded.
+    pub fn alloc_count(&self) -> usize {
+        self.alloc_count_with(Ordering::Acquire)
+    }
+
+    /// `alloc_count` with a caller-chosen load ordering (see `bytes_used_with`).
+    pub fn alloc_count_with(&self, ordering: Ordering) -> usize {
+        self.alloc_count.load(ordering)
+    }
+
+    /// How many allocations have failed for lack of room or an impossible
+    /// alignment, i.e. the ones an OOM handler would hear about. Unlike
+    /// `alloc_count`, this is never sampled.
+    pub fn oom_count(&self) -> usize {
+        self.oom_count.load(Ordering::Acquire)
+    }
+
+    /// Successful allocations bucketed by size: entry `i` counts sizes in
+    /// `[2^i, 2^(i+1))` (zero-sized allocations land in entry 0).
+    pub fn size_histogram(&self) -> [usize; HISTOGRAM_BUCKETS] {
+        core::array::from_fn(|i| self.size_histogram[i].load(Ordering::Acquire))
+    }
+
+    /// Successful allocations bucketed by requested alignment: entry `i`
+    /// counts alignment `2^i`. Handy for spotting over-aligned requests.
+    pub fn alignment_histogram(&self) -> [usize; HISTOGRAM_BUCKETS] {
+        core::array::from_fn(|i| self.align_histogram[i].load(Ordering::Acquire))
+    }
+
+    /// Only update `alloc_count` and the histograms on every `every`th
+    /// allocation (per thread), adding `every` each time, to take the
+    /// counter updates off the hot path. The figures become estimates,
+    /// exact to within `every` per thread. 0 and 1 both mean record
+    /// everything.
+    pub fn set_stats_sampling(&self, every: usize) {
+        self.sample_every.store(every.max(1), Ordering::Relaxed);
+    }
+
+    // `bumped` says whether the block came from the cursor, for `fast_allocs`.
+    fn record_alloc(&self, layout: Layout, bumped: bool) {
+        let every = self.sample_every.load(Ordering::Relaxed);
+        if every > 1 {
+            let sampled = SAMPLE_COUNTDOWN.with(|countdown| match countdown.get() {
+                0 => {
+                    countdown.set(every - 1);
+                    true
+                }
+                n => {
+                    countdown.set(n - 1);
+                    false
+                }
+            });
+            if !sampled {
+                return;
+            }
+        }
+
+        self.alloc_count.fetch_add(every, Ordering::Release);
+        self.size_histogram[layout.size().max(1).ilog2() as usize]
+            .fetch_add(every, Ordering::Release);
+        self.align_histogram[layout.align().ilog2() as usize].fetch_add(every, Ordering::Release);
+        if bumped {
+            self.fast_allocs.fetch_add(every, Ordering::Relaxed);
+        }
+    }
+
+    /// Allocate a block that lies entirely within `[heap_start + lo,
+    /// heap_start + hi)`, e.g. to keep it in the low part of the heap. If the
+    /// window starts past the cursor, the cursor jumps forward to it and the
+    /// skipped bytes are counted as padding. Returns null if the block doesn't
+    /// fit in the window.
+    pub fn alloc_in_range(&self, layout: Layout, lo: usize, hi: usize) -> *mut u8 {
+        let Ok(layout) = self.padded(layout) else {
+            return null_mut();
+        };
+        let window_start = self.heap_start().saturating_add(lo);
+        let window_end = self.heap_start().saturating_add(hi).min(self.heap_end());
+        let ptr = self
+            .bump_within(layout, window_start, window_end)
+            .map_or(null_mut(), |(ptr, _)| ptr.as_ptr());
+        #[cfg(feature = "record-ops")]
+        self.record_op(RecordedOp::AllocInRange {
+            size: layout.size(),
+            align: layout.align(),
+            lo,
+            hi,
+            offset: self.recorded_offset(ptr),
+        });
+        ptr
+    }
+
+    /// `GlobalAlloc::alloc`, but saying why it failed instead of returning null.
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        self.alloc_block(layout).map(|(ptr, _)| ptr)
+    }
+
+    /// `GlobalAlloc::alloc_zeroed`, but saying why it failed instead of
+    /// returning null.
+    pub fn try_alloc_zeroed(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        let (ptr, prev_high) = self.alloc_block(layout)?;
+
+        // Memory above the old high-water mark has never been handed out and
+        // is still zero. Anything below it is being reused (after a reset, a
+        // top-block free, or from the free list) and may hold old data, so
+        // only that part needs clearing.
+        let start = ptr.as_ptr() as usize;
+        let dirty_end = prev_high.min(start + layout.size());
+        if dirty_end > start {
+            unsafe { core::ptr::write_bytes(ptr.as_ptr(), 0, dirty_end - start) };
+        }
+        Ok(ptr)
+    }
+
+    /// A zeroed array of `n` `T`s, e.g. to build an arena-backed
+    /// `Box<[T]>`. An `n` so big the array's size overflows is
+    /// `AllocError::OutOfMemory`, like any other request too big to fit.
+    /// Only fill it with types for which all-zero bytes are a valid value.
+    pub fn try_alloc_array_zeroed<T>(&self, n: usize) -> Result<NonNull<[T]>, AllocError> {
+        let layout = Layout::array::<T>(n).map_err(|_| AllocError::OutOfMemory)?;
+        let ptr = self.try_alloc_zeroed(layout)?.cast::<T>();
+        Ok(NonNull::slice_from_raw_parts(ptr, n))
+    }
+
+    /// Allocate a block for each of `layouts` in one go, e.g. for the arrays
+    /// of a struct-of-arrays: either all of them fit and are returned in
+    /// order, or nothing is allocated. They're laid out back to back (each
+    /// aligned) in what is really a single block, so freeing any but the
+    /// last just writes it off.
+    pub fn bulk_alloc(&self, layouts: &[Layout]) -> Option<Vec<*mut u8>> {
+        let mut offsets = Vec::with_capacity(layouts.len());
+        let mut whole = Layout::new::<()>();
+        for &layout in layouts {
+            // Each piece is padded on its own, so freeing one with its own
+            // layout never reaches into the next.
+            let (extended, offset) = whole.extend(self.padded(layout).ok()?).ok()?;
+            whole = extended;
+            offsets.push(offset);
+        }
+        let base = self.try_alloc(whole).ok()?.as_ptr();
+        Some(
+            offsets
+                .into_iter()
+                .map(|offset| unsafe { base.add(offset) })
+                .collect(),
+        )
+    }
+
+    /// Allocate `size` bytes with no alignment at all, so consecutive blocks
+    /// are packed back to back with no padding, e.g. for building a packed
+    /// binary format. The result is only fit for byte-wise or unaligned
+    /// access. Returns null if there's no room.
+    pub fn alloc_packed(&self, size: usize) -> *mut u8 {
+        match Layout::from_size_align(size, 1) {
+            Ok(layout) => self.try_alloc(layout).map_or(null_mut(), NonNull::as_ptr),
+            Err(_) => null_mut(),
+        }
+    }
+
+    /// Start a group of allocations that should sit next to each other (the
+    /// nodes of one tree, say). The scope reserves a block up front and
+    /// sub-allocates from it, growing it in place while it's still the top
+    /// block; only if something else has been allocated since does it move
+    /// on to a fresh reservation. Unused reserved space is handed back when
+    /// the scope is dropped.
+    pub fn with_affinity(&self, tag: &'static str) -> Affinity<'_> {
+        let scope = Affinity {
+            bump: self,
+            tag,
+            start: Cell::new(0),
+            used: Cell::new(0),
+            len: Cell::new(0),
+        };
+        scope.reserve(AFFINITY_RESERVE.min(self.cursor_room(Ordering::Acquire)));
+        scope
+    }
+
+    /// Start a batch of small allocations with the same lifetime (the nodes of
+    /// an AST, say): it reserves `bytes` up front with a single bump, then
+    /// hands out pieces of that with a plain local cursor, no atomics.
+    /// Anything that doesn't fit in the reservation comes from the allocator
+    /// as usual. Unused reserved space is handed back when the batch is
+    /// dropped.
+    pub fn batch(&self, bytes: usize) -> Batch<'_> {
+        let start = Layout::from_size_align(bytes, WORD_SIZE)
+            .ok()
+            .and_then(|layout| self.try_alloc(layout).ok())
+            .map_or(0, |ptr| ptr.as_ptr() as usize);
+        let len = if start == 0 { 0 } else { self.block_size(bytes) };
+        Batch {
+            bump: self,
+            start,
+            end: start + len,
+            cursor: Cell::new(start),
+        }
+    }
+
+    /// Allocate from the cursor or not at all, for real-time code that can't
+    /// afford to wait: no free-list lookup (it takes a lock), no lazy heap
+    /// setup, no OOM handler. Returns null whenever the fast path can't
+    /// satisfy `layout`, even if `alloc` could. Tracking mode takes a lock
+    /// too, so it doesn't see these blocks: they're missing from
+    /// `allocations`, and freeing one leaves the tracker as it was.
+    pub fn alloc_fast(&self, layout: Layout) -> *mut u8 {
+        let Ok(layout) = self.padded(layout) else {
+            return null_mut();
+        };
+        if layout.align() > self.capacity() || self.poisoned.load(Ordering::Relaxed) {
+            return null_mut();
+        }
+        let block = if self.direction == Direction::Down {
+            self.bump_down(layout, self.heap_start(), self.heap_end())
         } else {
-            eprintln!("Out of memory in manual allocation!");
+            self.bump_placed(layout, self.heap_start(), self.heap_end(), |start| {
+                checked_align_up(start, layout.align())
+            })
+        };
+        let ptr = block.map_or(null_mut(), |(ptr, _)| ptr.as_ptr());
+        #[cfg(feature = "record-ops")]
+        self.record_op(RecordedOp::AllocFast {
+            size: layout.size(),
+            align: layout.align(),
+            offset: self.recorded_offset(ptr),
+        });
+        ptr
+    }
+
+    /// Round every allocation smaller than `bytes` up to `bytes`, trading a
+    /// little padding for fewer, more reusable free-list entries and less
+    /// tracking churn from tiny blocks. Frees use it to work out how much a
+    /// block really took, so set it before anything is allocated.
+    pub fn set_min_block_size(&self, bytes: usize) {
+        self.min_block_size.store(bytes, Ordering::Relaxed);
+    }
+
+    // How many bytes an allocation of `size` actually takes.
+    #[inline]
+    fn block_size(&self, size: usize) -> usize {
+        size.max(self.min_block_size.load(Ordering::Relaxed))
+    }
+
+    // `layout` with its size rounded up to the minimum block size. A minimum
+    // too big to be a `Layout` could never be allocated anyway.
+    fn padded(&self, layout: Layout) -> Result<Layout, AllocError> {
+        let size = self.block_size(layout.size());
+        if size == layout.size() {
+            return Ok(layout);
         }
+        Layout::from_size_align(size, layout.align()).map_err(|_| AllocError::OutOfMemory)
     }
 
-    // Note: We are not calling `dealloc` here since our naive bump allocator
-    // effectively doesn't handle real frees. The memory usage only grows upward.
-    // Everything is freed once the process ends.
+    /// Start putting freed non-top blocks on a free list so later allocations
+    /// can reuse them, rather than writing them off. Adjacent free blocks are
+    /// merged, and free space that ends up touching the cursor is handed back
+    /// to it.
+    pub fn enable_free_list(&self) {
+        self.reuse_freed.store(true, Ordering::SeqCst);
+    }
 
-    println!("Demo complete.");
-}
+    /// Like `enable_free_list`, but keep the free list's bookkeeping in
+    /// `table` instead of in headers inside the freed blocks. An overrun
+    /// past the end of an allocation then can't corrupt the list, and blocks
+    /// of any size or alignment can be listed. The catch is the fixed
+    /// capacity: once `table` is full, further non-mergeable frees are
+    /// written off as dead. Blocks already on an in-band list are moved over.
+    ///
+    /// # Safety
+    ///
+    /// `table` must stay valid, and not be used for anything else, for as
+    /// long as the allocator is.
+    pub unsafe fn enable_free_list_with_table(&self, table: *mut [FreeSpan]) {
+        let mut list = self.lock_free_list();
+        let mut side = FreeList::OutOfBand {
+            table: table as *mut FreeSpan,
+            capacity: table.len(),
+            len: 0,
+        };
+        let mut lost = 0;
+        list.for_each(|start, size| {
+            if !side.insert(start, size) {
+                lost += size;
+            }
+        });
+        *list = side;
+        self.free_listed.fetch_sub(lost, Ordering::Release);
+        self.reuse_freed.store(true, Ordering::SeqCst);
+    }
+
+    fn lock_free_list(&self) -> MutexGuard<'_, FreeList> {
+        // Nothing in here can panic halfway through an update, so a poisoned
+        // lock still guards a consistent list.
+        self.free_list.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start recording every live allocation made through `alloc` (and
+    /// everything built on it) in `table`, for leak hunting and debugging.
+    /// Blocks allocated before this, or once `table` is full, aren't
+    /// recorded. In debug builds each new block is also checked against the
+    /// live ones, panicking if they overlap.
+    ///
+    /// # Safety
+    ///
+    /// `table` must stay valid, and not be used for anything else, for as
+    /// long as the allocator is.
+    pub unsafe fn enable_tracking_with_table(&self, table: *mut [AllocInfo]) {
+        *self.lock_tracker() = Tracker {
+            table: table as *mut AllocInfo,
+            capacity: table.len(),
+            len: 0,
+        };
+        self.tracking.store(true, Ordering::SeqCst);
+    }
+
+    /// The live allocations recorded in tracking mode, sorted by offset.
+    pub fn allocations(&self) -> Vec<AllocInfo> {
+        // Size the vector before taking the lock: if this is the global
+        // allocator, allocating with the lock held would deadlock.
+        let mut out = Vec::with_capacity(self.lock_tracker().len);
+        let tracker = self.lock_tracker();
+        let live = tracker.entries();
+        out.extend_from_slice(&live[..live.len().min(out.capacity())]);
+        out
+    }
+
+    /// Just the offsets of the live allocations recorded in tracking mode,
+    /// ascending.
+    pub fn allocated_offsets(&self) -> Vec<usize> {
+        self.allocations().iter().map(|info| info.offset).collect()
+    }
+
+    /// The live allocations recorded in tracking mode that `rewind(marker)`
+    /// would free, i.e. any reaching above the marker, sorted by offset. For
+    /// asserting nothing still in use would be invalidated before rewinding.
+    /// Empty for a marker `rewind` would ignore.
+    pub fn allocations_above(&self, marker: Marker) -> Vec<AllocInfo> {
+        if !self.can_rewind_to(marker) {
+            return Vec::new();
+        }
+        let mark = marker.position - self.heap_start();
+        let mut above = self.allocations();
+        above.retain(|info| info.offset + info.size > mark);
+        above
+    }
 
+    fn lock_tracker(&self) -> MutexGuard<'_, Tracker> {
+        self.tracker.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn track_alloc(&self, ptr: NonNull<u8>, size: usize) {
+        if !self.tracking.load(Ordering::Acquire) {
+            return;
+        }
+        let info = AllocInfo {
+            offset: ptr.as_ptr() as usize - self.heap_start(),
+            size,
+        };
+        let overlap = self.lock_tracker().insert(info);
+        // Panic with the lock released, since the message may allocate.
+        #[cfg(debug_assertions)]
+        if let Some(live) = overlap {
+            panic!("allocator handed out {info:?}, overlapping live allocation {live:?}");
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = overlap;
+    }
+
+    fn track_free(&self, ptr: *mut u8, size: usize) {
+        if self.tracking.load(Ordering::Acquire) {
+            let offset = ptr as usize - self.heap_start();
+            self.lock_tracker().remove(AllocInfo { offset, size });
+        }
+    }
+
+    /// Grow the top block at `ptr` from `old_size` to `new_size` bytes in
+    /// place, if it is the top block and there's room.
+    fn grow_in_place(&self, ptr: *mut u8, old_size: usize, new_size: usize) -> bool {
+        let (old_size, new_size) = (self.block_size(old_size), self.block_size(new_size));
+        if !self.extend_top(ptr as usize + old_size, new_size - old_size) {
+            return false;
+        }
+        self.track_resize(ptr, old_size, new_size);
+        #[cfg(feature = "record-ops")]
+        self.record_op(RecordedOp::Grow {
+            offset: ptr as usize - self.heap_start(),
+            old_size,
+            new_size,
+        });
+        true
+    }
+
+    /// Shrink the top block at `ptr` from `old_size` to `new_size` bytes in
+    /// place, handing the tail back to the cursor, if it is the top block.
+    fn shrink_in_place(&self, ptr: *mut u8, old_size: usize, new_size: usize) -> bool {
+        let (old_size, new_size) = (self.block_size(old_size), self.block_size(new_size));
+        let (start, end) = (ptr as usize, ptr as usize + old_size);
+        if self.direction == Direction::Down
+            || self
+                .next
+                .compare_exchange(end, start + new_size, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+        {
+            return false;
+        }
+        self.track_resize(ptr, old_size, new_size);
+        #[cfg(feature = "record-ops")]
+        self.record_op(RecordedOp::Shrink {
+            offset: start - self.heap_start(),
+            old_size,
+            new_size,
+        });
+        true
+    }
+
+    fn track_resize(&self, ptr: *mut u8, old_size: usize, new_size: usize) {
+        if self.tracking.load(Ordering::Acquire) {
+            let offset = ptr as usize - self.heap_start();
+            self.lock_tracker().resize(AllocInfo { offset, size: old_size }, new_size);
+        }
+    }
+
+    /// Cut the block at `ptr` down from `old_size` bytes to its first
+    /// `new_size`, freeing the rest (rolling the cursor back if the block is
+    /// still the top one). How batches and affinity scopes hand back what
+    /// they didn't use of a reservation.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live block of `old_size` bytes (as handed out, not
+    /// rounded up), and `new_size` no bigger than that.
+    unsafe fn trim_block(&self, ptr: *mut u8, old_size: usize, new_size: usize) {
+        self.free_block(ptr.add(new_size), old_size - new_size);
+        self.track_resize(ptr, old_size, new_size);
+        #[cfg(feature = "record-ops")]
+        self.record_op(RecordedOp::Trim {
+            offset: ptr as usize - self.heap_start(),
+            old_size,
+            new_size,
+        });
+    }
+
+    /// Give back the `size` bytes at `ptr`, exactly as many as given, with no
+    /// rounding up to the minimum block size.
+    ///
+    /// # Safety
+    ///
+    /// `[ptr, ptr + size)` must be (part of) a live block from this
+    /// allocator, and not be used again.
+    unsafe fn free_block(&self, ptr: *mut u8, size: usize) {
+        self.track_free(ptr, size);
+        let start = ptr as usize;
+        let end = start + size;
+        if self.direction == Direction::Down {
+            // The newest block is the lowest one, so it's the cursor that
+            // sits at its start.
+            if self
+                .next
+                .compare_exchange(start, end, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+            {
+                self.dead_bytes.fetch_add(size, Ordering::Relaxed);
+            }
+            return;
+        }
+        let reuse = self.reuse_freed.load(Ordering::Acquire);
+        if self
+            .next
+            .compare_exchange(end, start, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            if reuse {
+                self.reclaim_free_tail();
+            }
+            return;
+        }
+
+        self.dead_bytes.fetch_add(size, Ordering::Relaxed);
+        if reuse && self.lock_free_list().insert(start, size) {
+            self.free_listed.fetch_add(size, Ordering::Release);
+        }
+    }
+
+    /// First-fit search of the free list. Leftovers on either side of the
+    /// block go back on the list if they're big enough to, and count as waste
+    /// otherwise.
+    fn alloc_from_free_list(&self, layout: Layout) -> Option<NonNull<u8>> {
+        if self.free_listed.load(Ordering::Acquire) == 0 {
+            return None;
+        }
+
+        #[cfg(debug_assertions)]
+        let _in_flight = InFlight::enter(&self.in_flight);
+        let mut list = self.lock_free_list();
+        let (start, size, aligned) = unsafe { list.take(layout)? };
+        self.free_listed.fetch_sub(size, Ordering::Release);
+        self.dead_bytes.fetch_sub(size, Ordering::Relaxed);
+
+        let front = aligned - start;
+        if front > 0 {
+            if unsafe { list.insert(start, front) } {
+                self.free_listed.fetch_add(front, Ordering::Release);
+                self.dead_bytes.fetch_add(front, Ordering::Relaxed);
+            } else {
+                self.wasted_padding.fetch_add(front, Ordering::Relaxed);
+            }
+        }
+        let used_end = aligned + layout.size();
+        let back = start + size - used_end;
+        if back > 0 {
+            if unsafe { list.insert(used_end, back) } {
+                self.free_listed.fetch_add(back, Ordering::Release);
+            }
+            self.dead_bytes.fetch_add(back, Ordering::Relaxed);
+        }
+
+        self.record_alloc(layout, false);
+        self.freelist_allocs.fetch_add(1, Ordering::Relaxed);
+        NonNull::new(aligned as *mut u8)
+    }
+
+    /// Hand free blocks that now end right at the cursor back to it, e.g.
+    /// after the top block was freed and the one below it already was.
+    fn reclaim_free_tail(&self) {
+        let mut list = self.lock_free_list();
+        loop {
+            let current_next = self.next.load(Ordering::Acquire);
+            let Some((start, size)) = list.last() else {
+                return;
+            };
+            if start + size != current_next {
+                return;
+            }
+            // Stop counting the block as listed before the cursor moves over
+            // it, as `rewind` does: `bytes_used_with` loads the cursor first,
+            // and must never see it below bytes still counted as listed.
+            self.free_listed.fetch_sub(size, Ordering::Release);
+            if self
+                .next
+                .compare_exchange(current_next, start, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                unsafe { list.remove_last() };
+                self.dead_bytes.fetch_sub(size, Ordering::Relaxed);
+                return;
+            }
+            self.free_listed.fetch_add(size, Ordering::Release);
+        }
+    }
+
+    /// Allocate `layout` positioned so that the field at `field_offset` bytes
+    /// into it lands on a `field_align` boundary (as well as the block itself
+    /// being `layout.align()` aligned), e.g. for descriptors whose hardware
+    /// visible part isn't at the front. Returns null if the two alignments
+    /// can't both be met or the block doesn't fit.
+    pub fn alloc_with_offset_align(
+        &self,
+        layout: Layout,
+        field_offset: usize,
+        field_align: usize,
+    ) -> *mut u8 {
+        let Ok(layout) = self.padded(layout) else {
+            return null_mut();
+        };
+        if self.check_layout(layout).is_err()
+            || self.direction == Direction::Down
+            || !field_align.is_power_of_two()
+            || field_align > self.capacity()
+            // Both are powers of two, so they only agree if the offset is a
+            // multiple of the smaller one.
+            || field_offset & (field_align.min(layout.align()) - 1) != 0
+        {
+            return null_mut();
+        }
+
+        let placed = self.bump_placed(layout, self.heap_start(), self.heap_end(), |start| {
+            if field_align <= layout.align() {
+                checked_align_up(start, layout.align())
+            } else {
+                let field = checked_align_up(start.checked_add(field_offset)?, field_align)?;
+                Some(field - field_offset)
+            }
+        });
+        let ptr = placed.map_or(null_mut(), |(ptr, _)| ptr.as_ptr());
+        #[cfg(feature = "record-ops")]
+        self.record_op(RecordedOp::AllocWithOffsetAlign {
+            size: layout.size(),
+            align: layout.align(),
+            field_offset,
+            field_align,
+            offset: self.recorded_offset(ptr),
+        });
+        ptr
+    }
+
+    // Where `ptr` is in the heap, for the op log; `None` for a failed
+    // allocation.
+    #[cfg(feature = "record-ops")]
+    fn recorded_offset(&self, ptr: *mut u8) -> Option<usize> {
+        (!ptr.is_null()).then(|| ptr as usize - self.heap_start())
+    }
+
+    /// Every allocation path starts here: refuse to go on if poisoned, set up
+    /// a lazy heap if this is the first allocation, then reject alignments or
+    /// sizes bigger than the whole heap. Those can never be satisfied, and
+    /// rounding up to them could overflow, so bail out before any arithmetic.
+    fn check_layout(&self, layout: Layout) -> Result<(), AllocError> {
+        if self.poisoned.load(Ordering::Relaxed) {
+            return Err(AllocError::Poisoned);
+        }
+        if let Some(region) = self.lazy_region {
+            self.lazy_init.call_once(|| {
+                let region = region();
+                unsafe { self.init(region.as_mut_ptr() as usize, region.len()) };
+                if self.lazy_zeroed {
+                    self.high_water.store(self.origin(), Ordering::SeqCst);
+                }
+            });
+        }
+        let capacity = self.heap_end() - self.heap_start();
+        if layout.align() > capacity {
+            return Err(AllocError::InvalidAlign);
+        }
+        if layout.size() > capacity {
+            return Err(AllocError::OutOfMemory);
+        }
+        Ok(())
+    }
+
+    /// Allocate from the free list if it has a fitting block, else bump. The
+    /// second value is as for `bump_within`; for a reused block it's
+    /// `usize::MAX` since the whole block may hold old data.
+    fn alloc_block(&self, layout: Layout) -> Result<(NonNull<u8>, usize), AllocError> {
+        let guard = PoisonOnUnwind(&self.poisoned);
+        let block = self.padded(layout).and_then(|padded| {
+            self.check_layout(padded)?;
+            if let Some(ptr) = self.alloc_from_free_list(padded) {
+                return Ok((ptr, usize::MAX));
+            }
+            self.bump_within(padded, self.heap_start(), self.heap_end())
+        });
+        #[cfg(feature = "record-ops")]
+        if !matches!(block, Err(AllocError::Poisoned)) {
+            self.record_op(RecordedOp::Alloc {
+                size: layout.size(),
+                align: layout.align(),
+                offset: block
+                    .as_ref()
+                    .ok()
+                    .map(|(ptr, _)| ptr.as_ptr() as usize - self.heap_start()),
+            });
+        }
+        match block {
+            Ok((ptr, _)) => {
+                self.track_alloc(ptr, self.block_size(layout.size()));
+                #[cfg(feature = "profile-ring")]
+                self.events
+                    .push(ptr.as_ptr() as usize - self.heap_start(), layout.size());
+            }
+            Err(AllocError::Poisoned) => {}
+            Err(error) => self.report_oom(layout, error),
+        }
+        core::mem::forget(guard);
+        block
+    }
+
+    /// Whether a panic (from the OOM handler, say) unwound through an
+    /// allocation. A poisoned allocator fails every allocation, returning
+    /// null or `AllocError::Poisoned`, rather than risk handing out memory
+    /// from half-updated bookkeeping. Frees and queries keep working. Only
+    /// the inherent allocation methods can poison it; the `GlobalAlloc` ones
+    /// abort instead.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
+    /// Move the allocation events recorded since the last drain into `out`,
+    /// oldest first. Meant for a single profiler thread; concurrent drains
+    /// take turns. If the ring fills up before it's drained, newer events are
+    /// dropped.
+    #[cfg(feature = "profile-ring")]
+    pub fn drain_events(&self, out: &mut Vec<AllocEvent>) {
+        self.events.drain(out);
+    }
+
+    /// Start recording every operation that changes the heap, for `op_log`.
+    /// The log (room for a few thousand operations) is allocated from the
+    /// system allocator, not the heap, and kept until the allocator is
+    /// dropped; enabling it again does nothing.
+    #[cfg(feature = "record-ops")]
+    pub fn enable_op_log(&self) {
+        if !self.ops.load(Ordering::Acquire).is_null() {
+            return;
+        }
+        let layout = Layout::new::<OpLog>();
+        // All zeroes is an empty log: no slots claimed or published.
+        let log = unsafe { std::alloc::System.alloc_zeroed(layout) }.cast::<OpLog>();
+        if log.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        if self
+            .ops
+            .compare_exchange(null_mut(), log, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            unsafe { std::alloc::System.dealloc(log.cast(), layout) };
+        }
+    }
+
+    /// Every operation since `enable_op_log` that changed the heap (each
+    /// kind of allocation, frees, in-place resizes, rewinds and resets),
+    /// oldest first, for reproducing a bug by `replay`ing them against a
+    /// fresh arena. Only the first few thousand are kept. Empty if recording
+    /// was never enabled. `rewind_full` is recorded as a plain rewind: the
+    /// statistics it restores aren't.
+    #[cfg(feature = "record-ops")]
+    pub fn op_log(&self) -> &[RecordedOp] {
+        match unsafe { self.ops.load(Ordering::Acquire).as_ref() } {
+            Some(log) => log.published(),
+            None => &[],
+        }
+    }
+
+    #[cfg(feature = "record-ops")]
+    fn record_op(&self, op: RecordedOp) {
+        // Only freed along with `self`.
+        if let Some(log) = unsafe { self.ops.load(Ordering::Acquire).as_ref() } {
+            log.push(op);
+        }
+    }
+
+    /// Have `handler` called whenever an allocation fails, e.g. to log what
+    /// was asked for before the global allocator's null turns into an abort.
+    /// It runs on the failing allocation's thread and, when this is the
+    /// global allocator, must not allocate. It shouldn't panic either: a
+    /// panic out of `try_alloc` and the other inherent methods poisons the
+    /// allocator (see `is_poisoned`), but one out of a `GlobalAlloc` method
+    /// aborts the process, since those must not unwind.
+    pub fn set_oom_handler(&self, handler: fn(&OomReport)) {
+        self.oom_handler.store(handler as usize, Ordering::Release);
+    }
+
+    fn report_oom(&self, layout: Layout, error: AllocError) {
+        self.oom_count.fetch_add(1, Ordering::Release);
+        let raw = self.oom_handler.load(Ordering::Acquire);
+        if raw == 0 {
+            return;
+        }
+        // Only ever stored from a `fn(&OomReport)` in `set_oom_handler`.
+        let handler = unsafe { core::mem::transmute::<usize, fn(&OomReport)>(raw) };
+        handler(&OomReport {
+            name: self.name,
+            layout,
+            error,
+            bytes_remaining: self.bytes_remaining(),
+            capacity: self.capacity(),
+        });
+    }
+
+    /// The core bump: place `layout` at the first aligned address at or after
+    /// both the cursor and `floor`, as long as it ends by `limit`.
+    ///
+    /// Alongside the block it returns the high-water mark from just before
+    /// the allocation; bytes of the block at or above it are still zero.
+    fn bump_within(
+        &self,
+        layout: Layout,
+        floor: usize,
+        limit: usize,
+    ) -> Result<(NonNull<u8>, usize), AllocError> {
+        self.check_layout(layout)?;
+        if self.direction == Direction::Down {
+            return self.bump_down(layout, floor, limit);
+        }
+
+        // Bump the pointer up to meet alignment requirements.
+        self.bump_placed(layout, floor, limit, |start| place_up(start, layout.align()))
+    }
+
+    /// `bump_within` with the block's address chosen by `place`, which maps
+    /// the lowest possible start to the address to actually use, or `None`
+    /// if getting there would overflow.
+    pub(crate) fn bump_placed(
+        &self,
+        layout: Layout,
+        floor: usize,
+        limit: usize,
+        place: impl Fn(usize) -> Option<usize>,
+    ) -> Result<(NonNull<u8>, usize), AllocError> {
+        #[cfg(debug_assertions)]
+        let _in_flight = InFlight::enter(&self.in_flight);
+        // current allocation pointer. Acquire pairs with the release in
+        // `reset` (and top-block `dealloc`s), so memory reused from before
+        // is only handed out once its old owners are done with it.
+        let mut current_next = self.next.load(Ordering::Acquire);
+
+        loop {
+            // The cursor ends up right after the block, not rounded up to the
+            // alignment: a `Layout`'s size needn't be a multiple of its align
+            // (size 3, align 4 is legal), and the next allocation re-aligns
+            // from wherever this one ended, so only the padding it actually
+            // needs is lost. Both the alignment and the size step are checked,
+            // since a heap right at the top of the address space could
+            // otherwise wrap around (or, saturating, seem to end in bounds).
+            let new_next = place(current_next.max(floor))
+                .and_then(|aligned| Some((aligned, aligned.checked_add(layout.size())?)));
+
+            // Check for out-of-memory
+            let Some((aligned, new_next)) = new_next.filter(|&(_, end)| end <= limit) else {
+                // Not enough space
+                return Err(AllocError::OutOfMemory);
+            };
+
+            // CAS loop since multiple threads might attempt allocations at once
+            // (and `dealloc` may roll the cursor back underneath us).
+            match self.next.compare_exchange_weak(
+                current_next,
+                new_next,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    self.wasted_padding
+                        .fetch_add(aligned - current_next, Ordering::Relaxed);
+                    let prev_high = self.high_water.fetch_max(new_next, Ordering::AcqRel);
+                    self.peak
+                        .fetch_max(new_next - self.heap_start(), Ordering::Release);
+                    self.record_alloc(layout, true);
+                    // `aligned` is inside the (non-null) heap.
+                    let ptr = unsafe { NonNull::new_unchecked(aligned as *mut u8) };
+                    return Ok((ptr, prev_high));
+                }
+                Err(actual) => current_next = actual,
+            }
+        }
+    }
+
+    /// `bump_within` for a downward heap: place `layout` at the highest
+    /// aligned address that ends at or below both the cursor and `limit`, as
+    /// long as it starts at or after `floor`. Bytes skipped between the block
+    /// and the cursor count as padding. Nothing below the cursor has been
+    /// zeroed since the heap was last handed out in this direction, so the
+    /// high-water value returned is always `usize::MAX`.
+    fn bump_down(
+        &self,
+        layout: Layout,
+        floor: usize,
+        limit: usize,
+    ) -> Result<(NonNull<u8>, usize), AllocError> {
+        #[cfg(debug_assertions)]
+        let _in_flight = InFlight::enter(&self.in_flight);
+        let mut current_next = self.next.load(Ordering::Acquire);
+
+        loop {
+            let aligned = match current_next.min(limit).checked_sub(layout.size()) {
+                Some(lowest) => lowest & !(layout.align() - 1),
+                None => return Err(AllocError::OutOfMemory),
+            };
+            if aligned < floor {
+                return Err(AllocError::OutOfMemory);
+            }
+
+            match self.next.compare_exchange_weak(
+                current_next,
+                aligned,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    self.wasted_padding.fetch_add(
+                        current_next - aligned - layout.size(),
+                        Ordering::Relaxed,
+                    );
+                    self.high_water.fetch_min(aligned, Ordering::AcqRel);
+                    self.peak
+                        .fetch_max(self.heap_end() - aligned, Ordering::Release);
+                    self.record_alloc(layout, true);
+                    // `aligned` is inside the (non-null) heap.
+                    let ptr = unsafe { NonNull::new_unchecked(aligned as *mut u8) };
+                    return Ok((ptr, usize::MAX));
+                }
+                Err(actual) => current_next = actual,
+            }
+        }
+    }
+
+    /// If the block ending at `end` is the top one, grow it in place by
+    /// `additional` bytes. A downward heap's newest block can only grow
+    /// towards lower addresses, i.e. by moving, so this always fails there.
+    fn extend_top(&self, end: usize, additional: usize) -> bool {
+        let new_end = end.saturating_add(additional);
+        if self.direction == Direction::Down
+            || new_end > self.heap_end()
+            || self
+                .next
+                .compare_exchange(end, new_end, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+        {
+            return false;
+        }
+        self.high_water.fetch_max(new_end, Ordering::AcqRel);
+        self.peak
+            .fetch_max(new_end - self.heap_start(), Ordering::Release);
+        true
+    }
+
+    /// Make room for `additional` more bytes after the first `old_len` bytes
+    /// at `ptr`, for append-heavy buffers. The top block grows in place and
+    /// keeps its address; any other block is copied to a fresh one (aligned
+    /// as well as the old one was, up to word alignment) and the old one
+    /// freed. Returns the buffer's address, or `None` if there's no room.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live block of `old_len` bytes from this allocator.
+    /// If it moves, the old address must not be used again.
+    pub unsafe fn grow_top_from(
+        &self,
+        ptr: *mut u8,
+        old_len: usize,
+        additional: usize,
+    ) -> Option<*mut u8> {
+        if self.grow_in_place(ptr, old_len, old_len.saturating_add(additional)) {
+            return Some(ptr);
+        }
+
+        let align = (1 << (ptr as usize).trailing_zeros()).min(WORD_SIZE);
+        let layout = checked_layout(old_len.checked_add(additional)?, align).ok()?;
+        let new = self.try_alloc(layout).ok()?.as_ptr();
+        core::ptr::copy_nonoverlapping(ptr, new, old_len);
+        self.dealloc(ptr, Layout::from_size_align_unchecked(old_len, align));
+        Some(new)
+    }
+
+    /// `realloc`, but to a possibly stronger alignment, e.g. to upgrade a
+    /// buffer for SIMD. It never weakens: a `new_align` below the old layout's
+    /// alignment is taken as the old one. A top block that already meets it
+    /// grows in place; anything else moves to a new block aligned to it.
+    /// Returns null, leaving the old block alone, if there's no room or the
+    /// new layout is invalid.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live block allocated from this allocator with
+    /// `old_layout`. If it moves, the old address must not be used again.
+    pub unsafe fn realloc_align(
+        &self,
+        ptr: *mut u8,
+        old_layout: Layout,
+        new_size: usize,
+        new_align: usize,
+    ) -> *mut u8 {
+        let new_align = new_align.max(old_layout.align());
+        let Ok(new_layout) = checked_layout(new_size, new_align) else {
+            return null_mut();
+        };
+        if ptr as usize & (new_align - 1) == 0
+            && new_size >= old_layout.size()
+            && self.grow_in_place(ptr, old_layout.size(), new_size)
+        {
+            return ptr;
+        }
+
+        let Ok((new, _)) = self.alloc_block(new_layout) else {
+            return null_mut();
+        };
+        let new = new.as_ptr();
+        core::ptr::copy_nonoverlapping(ptr, new, old_layout.size().min(new_size));
+        self.dealloc(ptr, old_layout);
+        new
+    }
+
+    /// Move the cursor back to the start of the heap, reclaiming everything.
+    /// Old data is left in place. The cursor is stored with release ordering
+    /// and allocations load it with acquire, so anything done with the old
+    /// blocks before the `reset` happens before the space is handed out
+    /// again.
+    ///
+    /// # Safety
+    ///
+    /// Every pointer handed out so far becomes dangling; none of them may be
+    /// used afterwards. No other thread may be allocating while it runs,
+    /// since an allocation straddling it could reuse a free-list block the
+    /// reset is about to give back to the cursor; debug builds panic if one
+    /// is caught in progress.
+    pub unsafe fn reset(&self) {
+        #[cfg(debug_assertions)]
+        assert!(
+            self.in_flight.load(Ordering::Acquire) == 0,
+            "bump allocator reset while an allocation was in progress"
+        );
+        #[cfg(feature = "record-ops")]
+        self.record_op(RecordedOp::Reset);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.lock_free_list().clear();
+        self.lock_tracker().clear();
+        self.free_listed.store(0, Ordering::SeqCst);
+        self.next.store(self.origin(), Ordering::Release);
+        self.wasted_padding.store(0, Ordering::Relaxed);
+        self.dead_bytes.store(0, Ordering::Relaxed);
+    }
+
+    /// Remember the current cursor position, to `rewind` back to later.
+    pub fn mark(&self) -> Marker {
+        Marker {
+            position: self.next.load(Ordering::Acquire),
+            wasted_padding: self.wasted_padding.load(Ordering::Relaxed),
+            dead_bytes: self.dead_bytes.load(Ordering::Relaxed),
+            generation: self.generation(),
+        }
+    }
+
+    /// Move the cursor back to `marker`, reclaiming everything allocated
+    /// since in one go. A marker from before the last `reset`, or outside
+    /// `[heap_start, cursor]`, is ignored, as is any marker on a downward
+    /// heap. The waste counters go back to what they were at the mark.
+    ///
+    /// # Safety
+    ///
+    /// Every pointer handed out since `marker` was taken becomes dangling;
+    /// none of them may be used afterwards.
+    pub unsafe fn rewind(&self, marker: Marker) {
+        if !self.can_rewind_to(marker) {
+            return;
+        }
+        #[cfg(feature = "record-ops")]
+        self.record_op(RecordedOp::Rewind {
+            offset: marker.position - self.heap_start(),
+            wasted_padding: marker.wasted_padding,
+            dead_bytes: marker.dead_bytes,
+        });
+
+        self.lock_tracker()
+            .truncate(marker.position - self.heap_start());
+        let mut list = self.lock_free_list();
+        let dropped = list.truncate(marker.position);
+        let listed = self.free_listed.fetch_sub(dropped, Ordering::Release) - dropped;
+        self.next.store(marker.position, Ordering::Release);
+        self.wasted_padding.store(marker.wasted_padding, Ordering::Relaxed);
+        // Older blocks freed since the mark may be on the free list, and
+        // listed bytes are always counted as dead.
+        self.dead_bytes
+            .store(marker.dead_bytes.max(listed), Ordering::Relaxed);
+    }
+
+    // Whether `rewind` would act on `marker` rather than ignore it.
+    fn can_rewind_to(&self, marker: Marker) -> bool {
+        marker.generation == self.generation()
+            && self.direction == Direction::Up
+            && (self.heap_start()..=self.next.load(Ordering::Acquire)).contains(&marker.position)
+    }
+
+    /// `mark`, also capturing the statistics, for `rewind_full`.
+    pub fn checkpoint_full(&self) -> FullCheckpoint {
+        FullCheckpoint {
+            marker: self.mark(),
+            alloc_count: self.alloc_count(),
+            fast_allocs: self.fast_allocs.load(Ordering::Relaxed),
+            freelist_allocs: self.freelist_allocs.load(Ordering::Relaxed),
+            peak: self.peak(),
+            size_histogram: self.size_histogram(),
+            alignment_histogram: self.alignment_histogram(),
+        }
+    }
+
+    /// `rewind`, and put `alloc_count` (with its split into fast and
+    /// free-list allocations), `peak` and the histograms back to what they
+    /// were at the checkpoint, so the rewound stretch doesn't show up in the
+    /// statistics either. The statistics are restored even if the rewind
+    /// itself is ignored (see `rewind`).
+    ///
+    /// # Safety
+    ///
+    /// Same as `rewind`.
+    pub unsafe fn rewind_full(&self, checkpoint: &FullCheckpoint) {
+        self.rewind(checkpoint.marker);
+        self.alloc_count.store(checkpoint.alloc_count, Ordering::Release);
+        self.fast_allocs.store(checkpoint.fast_allocs, Ordering::Relaxed);
+        self.freelist_allocs
+            .store(checkpoint.freelist_allocs, Ordering::Relaxed);
+        self.peak.store(checkpoint.peak, Ordering::Release);
+        let buckets = self.size_histogram.iter().chain(&self.align_histogram);
+        let counts = checkpoint
+            .size_histogram
+            .iter()
+            .chain(&checkpoint.alignment_histogram);
+        for (bucket, &count) in buckets.zip(counts) {
+            bucket.store(count, Ordering::Release);
+        }
+    }
+
+    /// Overwrite memory handed back by `free_above` with a poison pattern
+    /// (0xA5), so a stale pointer reads obvious garbage instead of plausible
+    /// old data.
+    pub fn set_poison(&self, enabled: bool) {
+        self.poison.store(enabled, Ordering::Relaxed);
+    }
+
+    /// `rewind` with the safety features applied: in poison mode everything
+    /// between `marker` and the old cursor is poisoned once it's reclaimed.
+    ///
+    /// # Safety
+    ///
+    /// Same as `rewind`.
+    pub unsafe fn free_above(&self, marker: Marker) {
+        let old_next = self.next.load(Ordering::Acquire);
+        self.rewind(marker);
+        if self.poison.load(Ordering::Relaxed)
+            && self.direction == Direction::Up
+            && self.next.load(Ordering::Acquire) == marker.position
+        {
+            let len = old_next - marker.position;
+            core::ptr::write_bytes(marker.position as *mut u8, POISON_BYTE, len);
+        }
+    }
+
+    /// Like `reset`, but also zeroes every byte that was ever handed out, so
+    /// nothing from before survives. Untouched memory above the high-water
+    /// mark is left alone.
+    ///
+    /// # Safety
+    ///
+    /// Same as `reset`.
+    pub unsafe fn clear_and_zero(&self) {
+        self.reset();
+        let old_high = self.high_water.swap(self.origin(), Ordering::SeqCst);
+        let (lo, hi) = match self.direction {
+            Direction::Up => (self.heap_start(), old_high),
+            Direction::Down => (old_high, self.heap_end()),
+        };
+        core::ptr::write_bytes(lo as *mut u8, 0, hi - lo);
+    }
+
+    /// How many times the heap has been `reset`. Anything allocated in an
+    /// earlier generation is gone.
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// The usage figures in one go. `fast_allocs` is sampled along with
+    /// `alloc_count` (see `set_stats_sampling`); `freelist_allocs` is always
+    /// exact. A lone allocator never falls back or grows.
+    pub fn stats(&self) -> BumpStats {
+        BumpStats {
+            bytes_used: self.bytes_used(),
+            peak: self.peak(),
+            alloc_count: self.alloc_count(),
+            fast_allocs: self.fast_allocs.load(Ordering::Relaxed),
+            freelist_allocs: self.freelist_allocs.load(Ordering::Relaxed),
+            fallback_allocs: 0,
+            grow_events: 0,
+        }
+    }
+
+    /// Write a short usage summary: allocation count, peak usage, and the
+    /// non-empty size-histogram buckets. It reads only the counters and
+    /// doesn't allocate, so it's safe to call at exit whatever state the
+    /// allocator is in.
+    pub fn write_summary(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(
+            out,
+            "{}: {} allocations, peak {} bytes",
+            self.name.unwrap_or("bump allocator"),
+            self.alloc_count(),
+            self.peak()
+        )?;
+        for (i, count) in self.size_histogram().into_iter().enumerate() {
+            if count != 0 {
+                writeln!(out, "  >= {} bytes: {}", 1usize << i, count)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Start the statistics over (e.g. after a warm-up phase) without
+    /// touching the cursor or any live allocation: `alloc_count`,
+    /// `oom_count` and the histograms go back to zero, and `peak` to the current usage. Unlike
+    /// `reset`, nothing is reclaimed, and the waste figures still describe
+    /// what's in the heap.
+    pub fn reset_stats(&self) {
+        self.alloc_count.store(0, Ordering::Release);
+        self.oom_count.store(0, Ordering::Release);
+        self.freelist_allocs.store(0, Ordering::Release);
+        self.fast_allocs.store(0, Ordering::Release);
+        for bucket in self.size_histogram.iter().chain(&self.align_histogram) {
+            bucket.store(0, Ordering::Release);
+        }
+        self.peak.store(self.bytes_used(), Ordering::Release);
+    }
+
+    /// Panic if anything is still allocated, i.e. it wasn't freed (top-down)
+    /// or wiped by a `reset`. Meant for test teardown. In tracking mode the
+    /// message lists the allocations still live, to point at the leak.
+    #[track_caller]
+    pub fn assert_no_leaks(&self) {
+        let leaked = self.bytes_used();
+        if leaked == 0 {
+            return;
+        }
+        let live = if self.tracking.load(Ordering::Acquire) {
+            let blocks: Vec<_> = self
+                .allocations()
+                .iter()
+                .map(|info| format!("{} bytes at offset {}", info.size, info.offset))
+                .collect();
+            format!("; still live: {}", blocks.join(", "))
+        } else {
+            String::new()
+        };
+        panic!(
+            "bump allocator {}leaked {} bytes ({} allocations made so far){}",
+            self.name.map(|name| format!("'{}' ", name)).unwrap_or_default(),
+            leaked,
+            self.alloc_count(),
+            live
+        );
+    }
+
+    /// Check the bookkeeping for consistency, e.g. after each step of a
+    /// randomized test: the cursor is inside the heap, free-list blocks and
+    /// tracked allocations lie below it without overlapping each other, and
+    /// used plus remaining bytes add up to the capacity. Only meaningful
+    /// while no other thread is allocating.
+    pub fn check_invariants(&self) -> Result<(), &'static str> {
+        let (start, end) = (self.heap_start(), self.heap_end());
+        let next = self.next.load(Ordering::Acquire);
+        if next < start || next > end {
+            return Err("cursor outside the heap");
+        }
+        // The part of the heap that has been handed out.
+        let (lo, hi) = match self.direction {
+            Direction::Up => (start, next),
+            Direction::Down => (next, end),
+        };
+        if self.free_listed.load(Ordering::Acquire) > hi - lo {
+            return Err("more bytes free-listed than handed out");
+        }
+        if self.bytes_used() + self.bytes_remaining() != self.capacity() {
+            return Err("used and remaining bytes don't add up to the capacity");
+        }
+
+        let mut free = Ok(());
+        let mut prev_end = lo;
+        self.lock_free_list().for_each(|block, size| {
+            if block < prev_end || block + size > hi {
+                free = Err("free-list block overlapping another or past the cursor");
+            }
+            prev_end = block + size;
+        });
+        free?;
+
+        // Zero-sized allocations occupy nothing, so they can share an address
+        // with a block or be left past a rolled-back cursor.
+        let tracker = self.lock_tracker();
+        let mut prev_end = lo - start;
+        for info in tracker.entries().iter().filter(|info| info.size != 0) {
+            if info.offset < prev_end || info.offset + info.size > hi - start {
+                return Err("tracked allocation overlapping another or past the cursor");
+            }
+            prev_end = info.offset + info.size;
+        }
+        Ok(())
+    }
+
+    /// Total bytes lost to internal fragmentation: alignment padding plus
+    /// freed-but-not-reclaimed (non-top) blocks.
+    pub fn bytes_wasted(&self) -> usize {
+        self.wasted_padding.load(Ordering::Relaxed) + self.dead_bytes.load(Ordering::Relaxed)
+    }
+}
+
+// With `single-threaded` the counters are `Cell`s, so the allocator mustn't be
+// shared between threads and is left `!Sync`.
+#[cfg(not(feature = "single-threaded"))]
+unsafe impl Sync for BumpAllocator {} // Required for global allocator, trivial here
+
+#[cfg(feature = "record-ops")]
+impl Drop for BumpAllocator {
+    fn drop(&mut self) {
+        let log = *self.ops.get_mut();
+        if !log.is_null() {
+            unsafe { std::alloc::System.dealloc(log.cast(), Layout::new::<OpLog>()) };
+        }
+    }
+}
+
+impl fmt::Debug for BumpAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BumpAllocator")
+            .field("name", &self.name)
+            .field("capacity", &self.capacity())
+            .field("bytes_used", &self.bytes_used())
+            .field("peak", &self.peak())
+            .field("alloc_count", &self.alloc_count())
+            .finish()
+    }
+}
+
+// 3. Implement `GlobalAlloc` for our `BumpAllocator`.
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        abort_on_unwind(|| self.try_alloc(layout).map_or(null_mut(), NonNull::as_ptr))
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        abort_on_unwind(|| self.try_alloc_zeroed(layout).map_or(null_mut(), NonNull::as_ptr))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // In a naive bump allocator, deallocation is a no-op or near no-op.
+        // The one case we can always handle is freeing the most recent (top)
+        // block: roll the cursor back so the space is reused. Anything else is
+        // dead until the whole heap goes away, unless the free list is on.
+        // Like `free(NULL)`, freeing null does nothing.
+        if ptr.is_null() {
+            return;
+        }
+        #[cfg(feature = "record-ops")]
+        self.record_op(RecordedOp::Dealloc {
+            offset: ptr as usize - self.heap_start(),
+            size: layout.size(),
+            align: layout.align(),
+        });
+        self.free_block(ptr, self.block_size(layout.size()));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        abort_on_unwind(|| {
+            // The top block can grow or shrink in place.
+            if new_size > layout.size() && self.grow_in_place(ptr, layout.size(), new_size) {
+                return ptr;
+            }
+            if new_size < layout.size() && self.shrink_in_place(ptr, layout.size(), new_size) {
+                return ptr;
+            }
+
+            // Otherwise it moves, keeping the old alignment so whatever type
+            // lives in the block is still aligned for it. `alloc_block` tries
+            // the free list before bumping, so a freed block of the right size
+            // gets reused rather than the heap growing.
+            let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+            let Ok((new, _)) = self.alloc_block(new_layout) else {
+                return null_mut();
+            };
+            let new = new.as_ptr();
+            core::ptr::copy_nonoverlapping(ptr, new, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+            new
+        })
+    }
+}
+
+// Run `f`, aborting the process if it panics. `GlobalAlloc` methods must not
+// unwind, and a panicking OOM handler (or debug self-check) would otherwise
+// unwind straight out of one.
+fn abort_on_unwind<R>(f: impl FnOnce() -> R) -> R {
+    struct Abort;
+
+    impl Drop for Abort {
+        fn drop(&mut self) {
+            std::process::abort();
+        }
+    }
+
+    let guard = Abort;
+    let result = f();
+    core::mem::forget(guard);
+    result
+}
+
+// Counts an allocation as in progress for as long as it's alive.
+#[cfg(debug_assertions)]
+struct InFlight<'a>(&'a Counter);
+
+#[cfg(debug_assertions)]
+impl<'a> InFlight<'a> {
+    fn enter(count: &'a Counter) -> Self {
+        count.fetch_add(1, Ordering::AcqRel);
+        InFlight(count)
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// Poisons the allocator if dropped, which only happens if the allocation it
+// guards unwinds; on the normal path it's forgotten.
+struct PoisonOnUnwind<'a>(&'a AtomicBool);
+
+impl Drop for PoisonOnUnwind<'_> {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+/// A set of allocators whose stats can be summed, e.g. one arena per thread
+/// for a whole-process view. It's an intrusive, lock-free list threaded
+/// through the allocators themselves, so each allocator can only be in one
+/// registry, and stays there.
+pub struct StatsRegistry {
+    head: AtomicPtr<BumpAllocator>,
+}
+
+impl StatsRegistry {
+    pub const fn new() -> Self {
+        StatsRegistry {
+            head: AtomicPtr::new(null_mut()),
+        }
+    }
+
+    /// Add `bump` to the registry. Returns false, and does nothing, if it's
+    /// already in one.
+    pub fn register(&self, bump: &'static BumpAllocator) -> bool {
+        if bump.registered.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        let node = bump as *const BumpAllocator as *mut BumpAllocator;
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            bump.registry_next.store(head, Ordering::Relaxed);
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return true,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    /// The registered allocators' `bytes_used`, `peak` and `alloc_count`,
+    /// each summed. The allocators are read one after another, so under
+    /// concurrent use the total isn't a single point in time.
+    pub fn aggregate_stats(&self) -> BumpStats {
+        let mut total = BumpStats::default();
+        let mut node = self.head.load(Ordering::Acquire);
+        while !node.is_null() {
+            // Only `'static` allocators are ever linked in.
+            let bump = unsafe { &*node };
+            total += bump.stats();
+            node = bump.registry_next.load(Ordering::Acquire);
+        }
+        total
+    }
+}
+
+impl Default for StatsRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One allocation, as recorded for an external profiler.
+#[cfg(feature = "profile-ring")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocEvent {
+    /// Offset of the block from the start of the heap.
+    pub offset: usize,
+    pub size: usize,
+    /// Nanoseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+#[cfg(feature = "profile-ring")]
+const PROFILE_RING_SLOTS: usize = 1024;
+
+// Fixed-size ring of allocation events. Pushing happens inside `alloc`, so it
+// takes a bounded number of steps and never allocates: it claims a slot with a
+// single CAS on `head` and gives up (dropping the event) if that fails or the
+// ring is full. Each slot's `stamp` is set to its index + 1 once written, so
+// the consumer never reads a claimed slot that isn't filled in yet.
+#[cfg(feature = "profile-ring")]
+struct EventRing {
+    slots: [EventSlot; PROFILE_RING_SLOTS],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    drain_lock: Mutex<()>,
+}
+
+#[cfg(feature = "profile-ring")]
+struct EventSlot {
+    stamp: AtomicUsize,
+    offset: AtomicUsize,
+    size: AtomicUsize,
+    timestamp: std::sync::atomic::AtomicU64,
+}
+
+#[cfg(feature = "profile-ring")]
+impl EventRing {
+    const fn new() -> Self {
+        EventRing {
+            slots: [const {
+                EventSlot {
+                    stamp: AtomicUsize::new(0),
+                    offset: AtomicUsize::new(0),
+                    size: AtomicUsize::new(0),
+                    timestamp: std::sync::atomic::AtomicU64::new(0),
+                }
+            }; PROFILE_RING_SLOTS],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            drain_lock: Mutex::new(()),
+        }
+    }
+
+    fn push(&self, offset: usize, size: usize) {
+        let head = self.head.load(Ordering::Relaxed);
+        if head - self.tail.load(Ordering::Acquire) >= PROFILE_RING_SLOTS
+            || self
+                .head
+                .compare_exchange(head, head + 1, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64);
+        let slot = &self.slots[head % PROFILE_RING_SLOTS];
+        slot.offset.store(offset, Ordering::Relaxed);
+        slot.size.store(size, Ordering::Relaxed);
+        slot.timestamp.store(timestamp, Ordering::Relaxed);
+        slot.stamp.store(head + 1, Ordering::Release);
+    }
+
+    fn drain(&self, out: &mut Vec<AllocEvent>) {
+        let _guard = self.drain_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[tail % PROFILE_RING_SLOTS];
+            if slot.stamp.load(Ordering::Acquire) != tail + 1 {
+                break;
+            }
+            let event = AllocEvent {
+                offset: slot.offset.load(Ordering::Relaxed),
+                size: slot.size.load(Ordering::Relaxed),
+                timestamp: slot.timestamp.load(Ordering::Relaxed),
+            };
+            tail += 1;
+            // Hand the slot back before pushing, which may itself allocate.
+            self.tail.store(tail, Ordering::Release);
+            out.push(event);
+        }
+    }
+}
+
+/// One allocator operation, as recorded for `BumpAllocator::op_log`. Offsets
+/// are from the start of the heap.
+#[cfg(feature = "record-ops")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordedOp {
+    /// An allocation, and where it landed (`None` if it failed).
+    Alloc {
+        size: usize,
+        align: usize,
+        offset: Option<usize>,
+    },
+    Dealloc {
+        offset: usize,
+        size: usize,
+        align: usize,
+    },
+    /// A top block grown in place by `realloc` and friends.
+    Grow {
+        offset: usize,
+        old_size: usize,
+        new_size: usize,
+    },
+    /// A top block shrunk in place by `realloc`.
+    Shrink {
+        offset: usize,
+        old_size: usize,
+        new_size: usize,
+    },
+    /// A block cut down to its first `new_size` bytes, the rest freed, by a
+    /// batch or affinity scope handing back unused reservation.
+    Trim {
+        offset: usize,
+        old_size: usize,
+        new_size: usize,
+    },
+    /// `alloc_fast`, and where it landed.
+    AllocFast {
+        size: usize,
+        align: usize,
+        offset: Option<usize>,
+    },
+    /// `alloc_in_range`, with its window, and where it landed.
+    AllocInRange {
+        size: usize,
+        align: usize,
+        lo: usize,
+        hi: usize,
+        offset: Option<usize>,
+    },
+    /// `alloc_with_offset_align`, and where it landed.
+    AllocWithOffsetAlign {
+        size: usize,
+        align: usize,
+        field_offset: usize,
+        field_align: usize,
+        offset: Option<usize>,
+    },
+    /// A rewind (by `rewind`, `free_above`, `free_generation` and the like)
+    /// back to the cursor at `offset`, and the waste counters it restored.
+    Rewind {
+        offset: usize,
+        wasted_padding: usize,
+        dead_bytes: usize,
+    },
+    Reset,
+}
+
+#[cfg(feature = "record-ops")]
+const OP_LOG_SLOTS: usize = 4096;
+
+// Append-only log of operations. An entry never changes once published, so
+// `published` can hand out the published prefix as a plain slice. Appending
+// happens inside `alloc`, so it never allocates: it claims an index, writes
+// the entry, then waits for the entries before it to be published so the
+// prefix has no holes. Once the log is full, later operations are dropped.
+#[cfg(feature = "record-ops")]
+struct OpLog {
+    slots: [UnsafeCell<core::mem::MaybeUninit<RecordedOp>>; OP_LOG_SLOTS],
+    claimed: AtomicUsize,
+    published: AtomicUsize,
+}
+
+// Slots are only written by the thread that claimed them, before they're
+// published, and only read after.
+#[cfg(feature = "record-ops")]
+unsafe impl Sync for OpLog {}
+
+#[cfg(feature = "record-ops")]
+impl OpLog {
+    fn push(&self, op: RecordedOp) {
+        let index = self.claimed.fetch_add(1, Ordering::Relaxed);
+        if index >= OP_LOG_SLOTS {
+            return;
+        }
+        unsafe { (*self.slots[index].get()).write(op) };
+        while self
+            .published
+            .compare_exchange_weak(index, index + 1, Ordering::Release, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn published(&self) -> &[RecordedOp] {
+        let len = self.published.load(Ordering::Acquire);
+        // The first `len` slots are written, and `MaybeUninit` and
+        // `UnsafeCell` don't change the layout.
+        unsafe { core::slice::from_raw_parts(self.slots.as_ptr().cast(), len) }
+    }
+}
+
+/// Run `ops` (say, another allocator's `op_log`) against `bump`, to reproduce
+/// whatever state they led to. Blocks are matched up by the offsets they were
+/// recorded at, so frees and growth hit the right block even if `bump`'s heap
+/// is aligned differently and places them elsewhere. Frees of blocks the
+/// replay didn't allocate are skipped. Rewinds go back to the recorded
+/// offset, so they only line up if the two heaps are aligned alike.
+///
+/// # Safety
+///
+/// Nothing else may be using `bump`: the ops may reset it, and any block the
+/// replay hands out is freed or kept as the ops say.
+#[cfg(feature = "record-ops")]
+pub unsafe fn replay(bump: &BumpAllocator, ops: &[RecordedOp]) {
+    let mut live = std::collections::HashMap::new();
+    for &op in ops {
+        match op {
+            RecordedOp::Alloc { size, align, offset } => {
+                let Ok(layout) = Layout::from_size_align(size, align) else {
+                    continue;
+                };
+                if let (Ok(ptr), Some(offset)) = (bump.try_alloc(layout), offset) {
+                    live.insert(offset, ptr.as_ptr());
+                }
+            }
+            RecordedOp::Dealloc { offset, size, align } => {
+                if let Some(ptr) = live.remove(&offset) {
+                    bump.dealloc(ptr, Layout::from_size_align_unchecked(size, align));
+                }
+            }
+            RecordedOp::Grow { offset, old_size, new_size } => {
+                if let Some(&ptr) = live.get(&offset) {
+                    bump.grow_in_place(ptr, old_size, new_size);
+                }
+            }
+            RecordedOp::Shrink { offset, old_size, new_size } => {
+                if let Some(&ptr) = live.get(&offset) {
+                    bump.shrink_in_place(ptr, old_size, new_size);
+                }
+            }
+            RecordedOp::Trim { offset, old_size, new_size } => {
+                if let Some(&ptr) = live.get(&offset) {
+                    bump.trim_block(ptr, old_size, new_size);
+                }
+            }
+            RecordedOp::AllocFast { size, align, offset } => {
+                let Ok(layout) = Layout::from_size_align(size, align) else {
+                    continue;
+                };
+                let ptr = bump.alloc_fast(layout);
+                if let (false, Some(offset)) = (ptr.is_null(), offset) {
+                    live.insert(offset, ptr);
+                }
+            }
+            RecordedOp::AllocInRange { size, align, lo, hi, offset } => {
+                let Ok(layout) = Layout::from_size_align(size, align) else {
+                    continue;
+                };
+                let ptr = bump.alloc_in_range(layout, lo, hi);
+                if let (false, Some(offset)) = (ptr.is_null(), offset) {
+                    live.insert(offset, ptr);
+                }
+            }
+            RecordedOp::AllocWithOffsetAlign {
+                size,
+                align,
+                field_offset,
+                field_align,
+                offset,
+            } => {
+                let Ok(layout) = Layout::from_size_align(size, align) else {
+                    continue;
+                };
+                let ptr = bump.alloc_with_offset_align(layout, field_offset, field_align);
+                if let (false, Some(offset)) = (ptr.is_null(), offset) {
+                    live.insert(offset, ptr);
+                }
+            }
+            RecordedOp::Rewind {
+                offset,
+                wasted_padding,
+                dead_bytes,
+            } => {
+                live.retain(|&block, _| block < offset);
+                bump.rewind(Marker {
+                    position: bump.heap_start() + offset,
+                    wasted_padding,
+                    dead_bytes,
+                    generation: bump.generation(),
+                });
+            }
+            RecordedOp::Reset => {
+                live.clear();
+                bump.reset();
+            }
+        }
+    }
+}
+
+// Header written at the start of each block on the free list, so the list
+// needs no storage of its own.
+#[repr(C)]
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+// Smallest block that can hold a `FreeBlock` header. Anything smaller (or not
+// word aligned) can't be listed unless it merges into a neighbour.
+const FREE_BLOCK_MIN: usize = core::mem::size_of::<FreeBlock>();
+
+/// One free region, as stored in an out-of-band free-list table.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FreeSpan {
+    start: usize,
+    size: usize,
+}
+
+/// A live allocation, as recorded in tracking mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocInfo {
+    /// Offset of the block from the start of the heap.
+    pub offset: usize,
+    pub size: usize,
+}
+
+// The live allocations in tracking mode, sorted by offset, in a table the
+// caller provides (so recording one never allocates).
+struct Tracker {
+    table: *mut AllocInfo,
+    capacity: usize,
+    len: usize,
+}
+
+// The table is only reached through the allocator's mutex.
+unsafe impl Send for Tracker {}
+
+impl Tracker {
+    const fn new() -> Self {
+        Tracker {
+            table: null_mut(),
+            capacity: 0,
+            len: 0,
+        }
+    }
+
+    fn entries(&self) -> &[AllocInfo] {
+        match self.len {
+            0 => &[],
+            len => unsafe { core::slice::from_raw_parts(self.table, len) },
+        }
+    }
+
+    fn entries_mut(&mut self) -> &mut [AllocInfo] {
+        match self.len {
+            0 => &mut [],
+            len => unsafe { core::slice::from_raw_parts_mut(self.table, len) },
+        }
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Record `info`, unless the table is full. Returns a live allocation it
+    /// overlaps, if any, which means the allocator has a bug.
+    fn insert(&mut self, info: AllocInfo) -> Option<AllocInfo> {
+        let entries = self.entries();
+        let at = entries.partition_point(|e| e.offset < info.offset);
+        // Zero-sized blocks can't overlap anything, so compare against the
+        // nearest real ones on either side.
+        let sized = |e: &&AllocInfo| e.size != 0;
+        let before = entries[..at].iter().rev().find(sized);
+        let after = entries[at..].iter().find(sized);
+        let overlap = before
+            .into_iter()
+            .chain(after)
+            .copied()
+            .filter(|_| info.size != 0)
+            .find(|e| e.offset < info.offset + info.size && info.offset < e.offset + e.size);
+        if self.len < self.capacity {
+            unsafe {
+                let slot = self.table.add(at);
+                core::ptr::copy(slot, slot.add(1), self.len - at);
+                slot.write(info);
+            }
+            self.len += 1;
+        }
+        overlap
+    }
+
+    fn position(&self, info: AllocInfo) -> Option<usize> {
+        let entries = self.entries();
+        let first = entries.partition_point(|e| e.offset < info.offset);
+        entries[first..]
+            .iter()
+            .take_while(|e| e.offset == info.offset)
+            .position(|e| e.size == info.size)
+            .map(|i| first + i)
+    }
+
+    fn remove(&mut self, info: AllocInfo) {
+        if let Some(i) = self.position(info) {
+            unsafe {
+                let slot = self.table.add(i);
+                core::ptr::copy(slot.add(1), slot, self.len - i - 1);
+            }
+            self.len -= 1;
+        }
+    }
+
+    fn resize(&mut self, info: AllocInfo, new_size: usize) {
+        if let Some(i) = self.position(info) {
+            self.entries_mut()[i].size = new_size;
+        }
+    }
+
+    /// Forget every allocation that reaches `offset` or beyond. (One that
+    /// straddles it was allocated after the cursor last dropped below
+    /// `offset`, so it's being reclaimed too.)
+    fn truncate(&mut self, offset: usize) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            let e = self.entries()[i];
+            if e.offset < offset && e.offset + e.size <= offset {
+                self.entries_mut()[kept] = e;
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+// The free blocks, sorted by address so physical neighbours are adjacent in
+// the list and can be merged on insert. Either threaded through the free
+// blocks themselves (no extra storage, but an overrun into a free block can
+// corrupt the list), or kept in a separate fixed-capacity table outside the
+// heap.
+enum FreeList {
+    InBand {
+        head: *mut FreeBlock,
+    },
+    OutOfBand {
+        table: *mut FreeSpan,
+        capacity: usize,
+        len: usize,
+    },
+}
+
+// The raw pointers only ever point into the heap the list belongs to (or its
+// side table), and the list is only touched under its mutex.
+unsafe impl Send for FreeList {}
+
+impl FreeList {
+    const fn new() -> Self {
+        FreeList::InBand { head: null_mut() }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            FreeList::InBand { head } => *head = null_mut(),
+            FreeList::OutOfBand { len, .. } => *len = 0,
+        }
+    }
+
+    /// Add `[start, start + size)`, merging it with free neighbours. Returns
+    /// false if the block couldn't be listed at all.
+    unsafe fn insert(&mut self, start: usize, size: usize) -> bool {
+        // An empty block (from a zero-sized allocation) has nothing to reuse.
+        if size == 0 {
+            return false;
+        }
+        match self {
+            FreeList::InBand { head } => Self::insert_in_band(head, start, size),
+            FreeList::OutOfBand {
+                table,
+                capacity,
+                len,
+            } => {
+                let spans = core::slice::from_raw_parts_mut(*table, *capacity);
+                Self::insert_out_of_band(spans, len, start, size)
+            }
+        }
+    }
+
+    unsafe fn insert_in_band(head: &mut *mut FreeBlock, start: usize, size: usize) -> bool {
+        let end = start + size;
+        let mut prev: *mut FreeBlock = null_mut();
+        let mut cur = *head;
+        while !cur.is_null() && (cur as usize) < start {
+            prev = cur;
+            cur = (*cur).next;
+        }
+
+        // Grow the block below into us, and then into the block above if
+        // that's now touching too.
+        if !prev.is_null() && prev as usize + (*prev).size == start {
+            (*prev).size += size;
+            if cur as usize == end {
+                (*prev).size += (*cur).size;
+                (*prev).next = (*cur).next;
+            }
+            return true;
+        }
+
+        if size < FREE_BLOCK_MIN || start & (core::mem::align_of::<FreeBlock>() - 1) != 0 {
+            return false;
+        }
+
+        let block = start as *mut FreeBlock;
+        if cur as usize == end {
+            block.write(FreeBlock {
+                size: size + (*cur).size,
+                next: (*cur).next,
+            });
+        } else {
+            block.write(FreeBlock { size, next: cur });
+        }
+        if prev.is_null() {
+            *head = block;
+        } else {
+            (*prev).next = block;
+        }
+        true
+    }
+
+    fn insert_out_of_band(spans: &mut [FreeSpan], len: &mut usize, start: usize, size: usize) -> bool {
+        let end = start + size;
+        let i = spans[..*len].partition_point(|span| span.start < start);
+        let merges_below = i > 0 && spans[i - 1].start + spans[i - 1].size == start;
+        let merges_above = i < *len && spans[i].start == end;
+
+        match (merges_below, merges_above) {
+            (true, true) => {
+                spans[i - 1].size += size + spans[i].size;
+                spans.copy_within(i + 1..*len, i);
+                *len -= 1;
+            }
+            (true, false) => spans[i - 1].size += size,
+            (false, true) => {
+                spans[i].start = start;
+                spans[i].size += size;
+            }
+            (false, false) => {
+                if *len == spans.len() {
+                    return false;
+                }
+                spans.copy_within(i..*len, i + 1);
+                spans[i] = FreeSpan { start, size };
+                *len += 1;
+            }
+        }
+        true
+    }
+
+    /// Whether some block could hold `layout`.
+    fn fits(&self, layout: Layout) -> bool {
+        let mut found = false;
+        self.for_each(|start, size| {
+            found |= checked_align_up(start, layout.align())
+                .and_then(|aligned| aligned.checked_add(layout.size()))
+                .is_some_and(|end| end <= start + size);
+        });
+        found
+    }
+
+    /// Unlink the first block that can hold `layout`, returning its start,
+    /// size and the aligned address to use within it.
+    unsafe fn take(&mut self, layout: Layout) -> Option<(usize, usize, usize)> {
+        let fits = |start: usize, size: usize| {
+            let aligned = checked_align_up(start, layout.align())?;
+            (aligned.checked_add(layout.size())? <= start + size).then_some(aligned)
+        };
+
+        match self {
+            FreeList::InBand { head } => {
+                let mut prev: *mut FreeBlock = null_mut();
+                let mut cur = *head;
+                while !cur.is_null() {
+                    let (start, size) = (cur as usize, (*cur).size);
+                    if let Some(aligned) = fits(start, size) {
+                        if prev.is_null() {
+                            *head = (*cur).next;
+                        } else {
+                            (*prev).next = (*cur).next;
+                        }
+                        return Some((start, size, aligned));
+                    }
+                    prev = cur;
+                    cur = (*cur).next;
+                }
+                None
+            }
+            FreeList::OutOfBand { table, len, .. } => {
+                let spans = core::slice::from_raw_parts_mut(*table, *len);
+                let (i, aligned) = spans
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, span)| Some((i, fits(span.start, span.size)?)))?;
+                let FreeSpan { start, size } = spans[i];
+                spans.copy_within(i + 1.., i);
+                *len -= 1;
+                Some((start, size, aligned))
+            }
+        }
+    }
+
+    /// Start and size of every free block, in address order.
+    fn for_each(&self, mut f: impl FnMut(usize, usize)) {
+        match self {
+            FreeList::InBand { head } => {
+                let mut cur = *head;
+                while !cur.is_null() {
+                    unsafe {
+                        f(cur as usize, (*cur).size);
+                        cur = (*cur).next;
+                    }
+                }
+            }
+            FreeList::OutOfBand { table, len, .. } => {
+                let spans = unsafe { core::slice::from_raw_parts(*table, *len) };
+                for span in spans {
+                    f(span.start, span.size);
+                }
+            }
+        }
+    }
+
+    /// Forget everything at or above `at` (the cursor is being moved back to
+    /// it), trimming a block that straddles it. Returns how many listed bytes
+    /// were dropped.
+    unsafe fn truncate(&mut self, at: usize) -> usize {
+        let mut dropped = 0;
+        match self {
+            FreeList::InBand { head } => {
+                let mut link: *mut *mut FreeBlock = head;
+                while !(*link).is_null() && (*link) as usize + (**link).size <= at {
+                    link = &mut (**link).next;
+                }
+                let mut cur = *link;
+                // A straddling block keeps its lower part, if that's still
+                // big enough to hold the header.
+                if !cur.is_null()
+                    && (cur as usize) < at
+                    && at - cur as usize >= FREE_BLOCK_MIN
+                {
+                    dropped += (*cur).size - (at - cur as usize);
+                    (*cur).size = at - cur as usize;
+                    link = &mut (*cur).next;
+                    cur = *link;
+                }
+                *link = null_mut();
+                while !cur.is_null() {
+                    dropped += (*cur).size;
+                    cur = (*cur).next;
+                }
+            }
+            FreeList::OutOfBand { table, len, .. } => {
+                let spans = core::slice::from_raw_parts_mut(*table, *len);
+                let keep = spans.partition_point(|span| span.start < at);
+                for span in &spans[keep..] {
+                    dropped += span.size;
+                }
+                if let Some(span) = spans[..keep].last_mut() {
+                    let end = span.start + span.size;
+                    if end > at {
+                        dropped += end - at;
+                        span.size = at - span.start;
+                    }
+                }
+                *len = keep;
+            }
+        }
+        dropped
+    }
+
+    /// Start and size of the highest-addressed block.
+    fn last(&self) -> Option<(usize, usize)> {
+        let mut last = None;
+        self.for_each(|start, size| last = Some((start, size)));
+        last
+    }
+
+    unsafe fn remove_last(&mut self) {
+        match self {
+            FreeList::InBand { head } => {
+                let mut link: *mut *mut FreeBlock = head;
+                while !(*link).is_null() {
+                    if (**link).next.is_null() {
+                        *link = null_mut();
+                        return;
+                    }
+                    link = &mut (**link).next;
+                }
+            }
+            FreeList::OutOfBand { len, .. } => *len = len.saturating_sub(1),
+        }
+    }
+}
+
+/// A standalone arena that owns its backing buffer. It's the same bump logic
+/// as the global allocator, just over a heap-allocated region instead of the
+/// static `HEAP`, which makes it handy for scratch space and experiments.
+pub struct Arena {
+    // Only held to keep the region alive; all access goes through `bump`.
+    _buf: Vec<u8>,
+    // Out-of-band free-list table, if one was asked for.
+    free_table: Option<Box<[FreeSpan]>>,
+    // Allocation table for tracking mode, if it's on.
+    track_table: Option<Box<[AllocInfo]>>,
+    // Open frame generations, oldest first: id, start marker, and whether
+    // it's been freed but is waiting on newer ones.
+    frames: Vec<(usize, Marker, bool)>,
+    next_frame: usize,
+    // What `heap_start` is aligned to within `_buf` (see `align_base_to`).
+    base_align: usize,
+    bump: BumpAllocator,
+}
+
+/// An arena's contents and cursor, as plain data: offsets, never pointers, so
+/// it can be saved (with the `serde` feature) and loaded into a fresh arena
+/// with `Arena::from_snapshot`, where everything sits at the same offsets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArenaSnapshot {
+    pub capacity: usize,
+    /// Offset of the cursor; `bytes` holds everything below it.
+    pub cursor: usize,
+    pub bytes: Vec<u8>,
+}
+
+/// An offset-based reference to a block inside an `Arena`. Since it doesn't
+/// hold a raw pointer, the block can be moved (see `Arena::compact_to`) and the
+/// handle rewritten to follow it. It also remembers the arena's generation, so
+/// using it after a `reset` is caught instead of reading someone else's data.
+#[derive(Clone, Copy, Debug)]
+pub struct Handle {
+    offset: usize,
+    layout: Layout,
+    live: bool,
+    generation: usize,
+}
+
+impl Handle {
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.live
+    }
+}
+
+// The typed allocation methods hand out `&mut` from `&self`, which is fine
+// because every call gets a distinct region of the arena.
+impl Arena {
+    /// An arena over a fresh, zeroed `capacity`-byte buffer that it owns and
+    /// frees when dropped. This is the way to get an allocator without
+    /// touching `static mut` or raw memory yourself.
+    pub fn new(capacity: usize) -> Self {
+        let mut buf = vec![0u8; capacity];
+        let bump = BumpAllocator::for_region(buf.as_mut_ptr() as usize, capacity);
+        Arena {
+            _buf: buf,
+            free_table: None,
+            track_table: None,
+            frames: Vec::new(),
+            next_frame: 0,
+            base_align: 1,
+            bump,
+        }
+    }
+
+    /// Move the arena onto a buffer over-allocated by `align - 1` bytes, with
+    /// its start bumped forward to an `align` boundary, e.g.
+    /// `Arena::new(n).align_base_to(4096)`. The first block needing up to
+    /// `align` then takes no padding, which helps workloads heavy on aligned
+    /// allocations, since a `Vec`'s buffer may only be 8-aligned. Capacity and
+    /// settings are kept; markers, handles and frame generations from before
+    /// the move are stale, as after a `reset`. Panics if `align` isn't a
+    /// power of two or something has already been allocated.
+    pub fn align_base_to(mut self, align: usize) -> Self {
+        assert!(align.is_power_of_two(), "base alignment must be a power of two");
+        assert!(
+            self.bump.bytes_used() == 0,
+            "align_base_to called on an arena that's in use"
+        );
+        let capacity = self.bump.capacity();
+        self._buf = vec![0u8; capacity + align - 1];
+        let start = align_up(self._buf.as_mut_ptr() as usize, align);
+        self.bump.heap_start.store(start, Ordering::Release);
+        self.bump.heap_end.store(start + capacity, Ordering::Release);
+        self.bump.region_end.store(start + capacity, Ordering::Release);
+        self.bump.next.store(self.bump.origin(), Ordering::Release);
+        self.bump.high_water.store(self.bump.origin(), Ordering::Release);
+        // Anything taken against the old buffer points into freed memory.
+        self.bump.generation.fetch_add(1, Ordering::SeqCst);
+        self.frames.clear();
+        self.base_align = align;
+        self
+    }
+
+    /// Turn on the free list with its bookkeeping in an arena-owned side
+    /// table of `capacity` entries rather than inline in the heap (see
+    /// `BumpAllocator::enable_free_list_with_table`).
+    pub fn enable_free_list_out_of_band(&mut self, capacity: usize) {
+        let mut table = vec![FreeSpan::default(); capacity].into_boxed_slice();
+        unsafe { self.bump.enable_free_list_with_table(&mut *table) };
+        // Moving the box doesn't move the table, so the pointer stays valid.
+        self.free_table = Some(table);
+    }
+
+    /// Turn on tracking mode, recording up to `capacity` live allocations
+    /// (see `BumpAllocator::enable_tracking_with_table`).
+    pub fn enable_tracking(&mut self, capacity: usize) {
+        let mut table = vec![AllocInfo::default(); capacity].into_boxed_slice();
+        unsafe { self.bump.enable_tracking_with_table(&mut *table) };
+        self.track_table = Some(table);
+    }
+
+    /// Copy out the used bytes and cursor, leaving the arena as it is.
+    pub fn snapshot(&self) -> ArenaSnapshot {
+        let start = self.bump.heap_start();
+        let cursor = self.bump.next.load(Ordering::Acquire) - start;
+        ArenaSnapshot {
+            capacity: self.bump.capacity(),
+            cursor,
+            bytes: unsafe { core::slice::from_raw_parts(start as *const u8, cursor) }.to_vec(),
+        }
+    }
+
+    /// The used part of the arena (from the start to the cursor, padding
+    /// included) as `chunk`-byte slices, the last possibly shorter, e.g. to
+    /// stream it to a file without copying it first. Panics if `chunk` is 0.
+    pub fn iter_bytes_chunked(&self, chunk: usize) -> impl Iterator<Item = &[u8]> {
+        let start = self.bump.heap_start();
+        let used = self.bump.next.load(Ordering::Acquire) - start;
+        unsafe { core::slice::from_raw_parts(start as *const u8, used) }.chunks(chunk)
+    }
+
+    /// A new arena holding `snapshot`'s bytes, with its cursor where the
+    /// snapshot's was. Returns `None` if the snapshot is inconsistent.
+    /// Offsets are preserved but the buffer's own address alignment may
+    /// differ, so data needing more than byte alignment should be re-checked.
+    pub fn from_snapshot(snapshot: &ArenaSnapshot) -> Option<Self> {
+        if snapshot.bytes.len() != snapshot.cursor || snapshot.cursor > snapshot.capacity {
+            return None;
+        }
+        let mut arena = Arena::new(snapshot.capacity);
+        arena._buf[..snapshot.cursor].copy_from_slice(&snapshot.bytes);
+        let next = arena.bump.heap_start() + snapshot.cursor;
+        arena.bump.next.store(next, Ordering::Release);
+        arena.bump.high_water.store(next, Ordering::Release);
+        arena.bump.peak.store(snapshot.cursor, Ordering::Release);
+        Some(arena)
+    }
+
+    /// Take a copy of everything allocated so far (the bytes from the start
+    /// of the arena up to the cursor, padding included) and reset the arena,
+    /// for "build it in the arena, then hand it off" code.
+    pub fn drain(&mut self) -> Vec<u8> {
+        let start = self.bump.heap_start();
+        let used = self.bump.next.load(Ordering::Acquire) - start;
+        let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, used) }.to_vec();
+        // `&mut self` means no references into the arena are still around.
+        unsafe { self.bump.reset() };
+        self.frames.clear();
+        bytes
+    }
+
+    /// Start a new frame generation (not to be confused with the reset count,
+    /// `generation`): everything allocated from here on belongs to it until
+    /// the next `begin_generation`. Returns its id for `free_generation`.
+    pub fn begin_generation(&mut self) -> usize {
+        let id = self.next_frame;
+        self.next_frame += 1;
+        self.frames.push((id, self.bump.mark(), false));
+        id
+    }
+
+    /// Reclaim everything allocated in frame generation `id`. Only the newest
+    /// generation can actually be rewound; freeing an older one is deferred
+    /// until every generation after it has been freed too. Returns whether
+    /// the cursor moved.
+    ///
+    /// # Safety
+    ///
+    /// Pointers into the freed generation, and into any deferred ones
+    /// reclaimed along with it, become dangling.
+    pub unsafe fn free_generation(&mut self, id: usize) -> bool {
+        match self.frames.iter_mut().find(|frame| frame.0 == id) {
+            Some(frame) => frame.2 = true,
+            None => return false,
+        }
+        let mut rewound = false;
+        while let Some(&(_, start, true)) = self.frames.last() {
+            self.frames.pop();
+            self.bump.rewind(start);
+            rewound = true;
+        }
+        rewound
+    }
+
+    /// The usage figures as a single-line JSON object, for monitoring
+    /// pipelines: `capacity`, `used`, `peak`, `alloc_count`, `oom_count`, and
+    /// `size_histogram`, an array whose entry `i` counts allocation sizes in
+    /// `[2^i, 2^(i+1))`.
+    #[cfg(feature = "json")]
+    pub fn memory_report(&self) -> String {
+        use core::fmt::Write;
+
+        let mut out = format!(
+            "{{\"capacity\":{},\"used\":{},\"peak\":{},",
+            self.bump.capacity(),
+            self.bump.bytes_used(),
+            self.bump.peak()
+        );
+        let _ = write!(
+            out,
+            "\"alloc_count\":{},\"oom_count\":{},\"size_histogram\":[",
+            self.bump.alloc_count(),
+            self.bump.oom_count()
+        );
+        for (i, count) in self.bump.size_histogram().into_iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            let _ = write!(out, "{}{}", sep, count);
+        }
+        out.push_str("]}");
+        out
+    }
+
+    /// Whether both arenas have used the same number of bytes and those bytes
+    /// (from the start to the cursor, padding included) are identical. For
+    /// tests comparing a reference arena against a candidate.
+    pub fn content_eq(&self, other: &Arena) -> bool {
+        let used = |arena: &Arena| {
+            let start = arena.bump.heap_start();
+            let len = arena.bump.next.load(Ordering::Acquire) - start;
+            unsafe { core::slice::from_raw_parts(start as *const u8, len) }
+        };
+        used(self) == used(other)
+    }
+
+    /// Reset the arena and shrink its buffer to `new_capacity` bytes, giving
+    /// the rest back to the system, e.g. after a spike. Asking for more than
+    /// the current capacity just resets. The buffer may move, but `&mut self`
+    /// means nothing in it is still borrowed. Any `align_base_to` alignment
+    /// is kept.
+    pub fn reset_to_capacity(&mut self, new_capacity: usize) {
+        let old_base = self.bump.heap_start() - self._buf.as_ptr() as usize;
+        let dirty = self.bump.high_water.load(Ordering::Acquire) - self.bump.heap_start();
+        unsafe { self.bump.reset() };
+        self.frames.clear();
+        if new_capacity >= self.bump.capacity() {
+            return;
+        }
+
+        let len = new_capacity + self.base_align - 1;
+        self._buf.truncate(len);
+        self._buf.shrink_to(len);
+        let start = align_up(self._buf.as_mut_ptr() as usize, self.base_align);
+        let base = start - self._buf.as_ptr() as usize;
+        self.bump.heap_start.store(start, Ordering::Release);
+        self.bump.heap_end.store(start + new_capacity, Ordering::Release);
+        self.bump.region_end.store(start + new_capacity, Ordering::Release);
+        self.bump.next.store(start, Ordering::Release);
+        // The bytes kept were copied over as they were, used ones included,
+        // though a move may have shifted where the aligned start falls.
+        let dirty = (old_base + dirty).saturating_sub(base);
+        self.bump
+            .high_water
+            .store(start + dirty.min(new_capacity), Ordering::Release);
+    }
+
+    /// Label the arena in OOM reports, leak reports and `Debug` output.
+    pub fn set_name(&mut self, name: &'static str) {
+        self.bump.set_name(name);
+    }
+
+    /// Allocate a block and refer to it by offset rather than by pointer.
+    pub fn alloc_handle(&self, layout: Layout) -> Option<Handle> {
+        let ptr = unsafe { self.bump.alloc(layout) };
+        if ptr.is_null() {
+            return None;
+        }
+        Some(Handle {
+            offset: ptr as usize - self.bump.heap_start(),
+            layout,
+            live: true,
+            generation: self.bump.generation(),
+        })
+    }
+
+    /// Current address of a live handle's block, or `None` if it was freed
+    /// or the arena has been reset since it was allocated.
+    pub fn resolve(&self, handle: &Handle) -> Option<*mut u8> {
+        (handle.live && !self.is_stale(handle))
+            .then(|| (self.bump.heap_start() + handle.offset) as *mut u8)
+    }
+
+    /// The bytes of a live handle's block wherever it currently is, e.g.
+    /// after `compact_to` moved it. Panics if the handle was freed or the
+    /// arena has been reset since it was allocated.
+    pub fn used_slice_for(&self, handle: Handle) -> &[u8] {
+        match self.resolve(&handle) {
+            Some(ptr) => unsafe { core::slice::from_raw_parts(ptr, handle.layout.size()) },
+            None => panic!(
+                "arena {:?}: handle at offset {} is no longer live",
+                self.bump.name().unwrap_or("<unnamed>"),
+                handle.offset
+            ),
+        }
+    }
+
+    /// Whether the arena was reset after `handle` was allocated.
+    pub fn is_stale(&self, handle: &Handle) -> bool {
+        handle.generation != self.bump.generation()
+    }
+
+    pub fn free_handle(&self, handle: &mut Handle) {
+        if let Some(ptr) = self.resolve(handle) {
+            unsafe { self.bump.dealloc(ptr, handle.layout) };
+            handle.live = false;
+        }
+    }
+
+    /// Move `value` into the arena and return a reference to it, panicking
+    /// if there's no room. The arena never runs destructors, so `T` should
+    /// be `Copy` or otherwise fine to leak.
+    ///
+    /// This shadows `GlobalAlloc::alloc`, which the arena otherwise offers
+    /// through its `Deref`; `arena.bump.alloc(layout)` still reaches that
+    /// one. Passing a `Layout` here panics rather than quietly moving the
+    /// layout itself into the arena.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc<T>(&self, value: T) -> &mut T {
+        assert!(
+            core::any::type_name::<T>() != core::any::type_name::<Layout>(),
+            "Arena::alloc moves a value into the arena; allocate a Layout with try_alloc"
+        );
+        match self.try_alloc_value(value) {
+            Some(slot) => slot,
+            None => panic!(
+                "arena {:?} out of memory allocating {} bytes",
+                self.bump.name().unwrap_or("<unnamed>"),
+                core::mem::size_of::<T>()
+            ),
+        }
+    }
+
+    /// `alloc`, returning `None` instead of panicking when there's no room.
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_alloc_value<T>(&self, value: T) -> Option<&mut T> {
+        let ptr = self.bump.try_alloc(Layout::new::<T>()).ok()?.as_ptr() as *mut T;
+        unsafe {
+            ptr.write(value);
+            Some(&mut *ptr)
+        }
+    }
+
+    /// Like `try_alloc_value`, but reserves the slot before calling `f`, so
+    /// its result can be written straight into the arena instead of passing
+    /// through the stack (when the optimizer inlines `f`). Worth it for big
+    /// values. `f` isn't called if there's no room.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_with<T>(&self, f: impl FnOnce() -> T) -> Option<&mut T> {
+        let ptr = self.bump.try_alloc(Layout::new::<T>()).ok()?.as_ptr() as *mut T;
+        unsafe {
+            ptr.write(f());
+            Some(&mut *ptr)
+        }
+    }
+
+    /// Reserve `len` bytes to be filled in bit by bit, as a slice that can be
+    /// written through safely; `Arena::assume_init` turns it into `&mut [u8]`
+    /// once every byte is written. Returns `None` if there's no room.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_uninit_slice(&self, len: usize) -> Option<&mut [core::mem::MaybeUninit<u8>]> {
+        let ptr = self.bump.try_alloc(Layout::array::<u8>(len).ok()?).ok()?.as_ptr();
+        Some(unsafe { core::slice::from_raw_parts_mut(ptr.cast(), len) })
+    }
+
+    /// View a slice from `alloc_uninit_slice` as the bytes written to it.
+    ///
+    /// # Safety
+    ///
+    /// Every byte of `slice` must have been written.
+    pub unsafe fn assume_init(slice: &mut [core::mem::MaybeUninit<u8>]) -> &mut [u8] {
+        &mut *(slice as *mut [core::mem::MaybeUninit<u8>] as *mut [u8])
+    }
+
+    /// Copy the bytes at `[offset, offset + len)` into a new block and return
+    /// it, e.g. to version one piece of data without copying the whole
+    /// arena. Returns `None` if the range isn't within the used part of the
+    /// arena or there's no room for the copy.
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_clone_region(&self, offset: usize, len: usize) -> Option<&mut [u8]> {
+        let start = self.bump.heap_start();
+        let used = self.bump.next.load(Ordering::Acquire) - start;
+        if offset.checked_add(len)? > used {
+            return None;
+        }
+        let copy = self.bump.try_alloc(Layout::array::<u8>(len).ok()?).ok()?.as_ptr();
+        unsafe {
+            core::ptr::copy_nonoverlapping((start + offset) as *const u8, copy, len);
+            Some(core::slice::from_raw_parts_mut(copy, len))
+        }
+    }
+
+    /// Collect `iter` into a slice in the arena, reserving `iter.len()`
+    /// elements up front. If the iterator turns out to yield a different
+    /// number of items than it claimed, nothing past the reservation is
+    /// written: the items so far are dropped, the space handed back, and
+    /// `None` returned. As with everything in the arena, the elements'
+    /// destructors never run once the slice is returned.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_iter<T, I>(&self, iter: I) -> Option<&mut [T]>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let mut iter = iter.into_iter();
+        let len = iter.len();
+        let layout = Layout::array::<T>(len).ok()?;
+        let ptr = self.bump.try_alloc(layout).ok()?.as_ptr() as *mut T;
+
+        let mut written = 0;
+        while written < len {
+            let Some(item) = iter.next() else { break };
+            unsafe { ptr.add(written).write(item) };
+            written += 1;
+        }
+
+        if written < len || iter.next().is_some() {
+            unsafe {
+                core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(ptr, written));
+                self.bump.dealloc(ptr as *mut u8, layout);
+            }
+            return None;
+        }
+        Some(unsafe { core::slice::from_raw_parts_mut(ptr, len) })
+    }
+
+    /// Copy every live block referenced by `handles` into `dest`, packed
+    /// tightly, and rewrite the handles to point there. Dead handles are
+    /// skipped. Afterwards `self` can simply be dropped.
+    ///
+    /// Returns false (leaving `handles` untouched, and `dest` as it was) if
+    /// `dest` runs out of room.
+    pub fn compact_to(&self, dest: &mut Arena, handles: &mut [Handle]) -> bool {
+        let mark = dest.mark();
+        let mut moved: Vec<Option<Handle>> = Vec::with_capacity(handles.len());
+        for handle in handles.iter() {
+            let Some(src) = self.resolve(handle) else {
+                moved.push(None);
+                continue;
+            };
+            let Some(new) = dest.alloc_handle(handle.layout) else {
+                // Give back the copies made so far: everything bumped since
+                // the mark in one go, then any blocks reused from `dest`'s
+                // free list, which sit below it. `&mut` means nobody else
+                // has allocated from `dest` in the meantime.
+                unsafe { dest.rewind(mark) };
+                let below = mark.position - dest.heap_start();
+                for mut new in moved.into_iter().flatten() {
+                    if new.offset < below {
+                        dest.free_handle(&mut new);
+                    }
+                }
+                return false;
+            };
+            unsafe {
+                let dst = dest.resolve(&new).unwrap();
+                core::ptr::copy_nonoverlapping(src, dst, handle.layout.size());
+            }
+            moved.push(Some(new));
+        }
+
+        for (handle, new) in handles.iter_mut().zip(moved) {
+            if let Some(new) = new {
+                *handle = new;
+            }
+        }
+        true
+    }
+}
+
+impl fmt::Debug for Arena {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Arena")
+            .field("name", &self.bump.name)
+            .field("capacity", &self.bump.capacity())
+            .field("bytes_used", &self.bump.bytes_used())
+            .finish()
+    }
+}
+
+impl core::ops::Deref for Arena {
+    type Target = BumpAllocator;
+
+    fn deref(&self) -> &BumpAllocator {
+        &self.bump
+    }
+}
+
+/// A growable array in a bump allocator that avoids `Vec`'s copy-on-grow:
+/// while its buffer is still the top block it grows in place, and it only
+/// moves (to a buffer twice the size) once something else has been allocated
+/// after it. The elements are dropped, and the buffer freed, on drop.
+pub struct ArenaVec<'a, T> {
+    bump: &'a BumpAllocator,
+    ptr: NonNull<T>,
+    len: usize,
+    cap: usize,
+}
+
+impl<'a, T> ArenaVec<'a, T> {
+    pub fn new_in(bump: &'a BumpAllocator) -> Self {
+        ArenaVec {
+            bump,
+            ptr: NonNull::dangling(),
+            len: 0,
+            // Zero-sized elements never need room.
+            cap: if core::mem::size_of::<T>() == 0 { usize::MAX } else { 0 },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Append `value`, panicking if the allocator is out of room.
+    pub fn push(&mut self, value: T) {
+        if self.len == self.cap && !self.grow() {
+            panic!(
+                "allocator {:?} out of memory growing an ArenaVec",
+                self.bump.name().unwrap_or("<unnamed>")
+            );
+        }
+        unsafe { self.ptr.as_ptr().add(self.len).write(value) };
+        self.len += 1;
+    }
+
+    fn grow(&mut self) -> bool {
+        let new_cap = (self.cap * 2).max(4);
+        let Ok(new_layout) = Layout::array::<T>(new_cap) else {
+            return false;
+        };
+        if self.cap == 0 {
+            return match self.bump.try_alloc(new_layout) {
+                Ok(ptr) => {
+                    self.ptr = ptr.cast();
+                    self.cap = new_cap;
+                    true
+                }
+                Err(_) => false,
+            };
+        }
+
+        let old_layout = Layout::array::<T>(self.cap).unwrap();
+        let old = self.ptr.as_ptr() as *mut u8;
+        if self.bump.grow_in_place(old, old_layout.size(), new_layout.size()) {
+            self.cap = new_cap;
+            return true;
+        }
+        let Ok(new) = self.bump.try_alloc(new_layout) else {
+            return false;
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.ptr.as_ptr(), new.as_ptr() as *mut T, self.len);
+            self.bump.dealloc(old, old_layout);
+        }
+        self.ptr = new.cast();
+        self.cap = new_cap;
+        true
+    }
+}
+
+impl<T> core::ops::Deref for ArenaVec<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T> core::ops::DerefMut for ArenaVec<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T> Drop for ArenaVec<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            core::ptr::drop_in_place(&mut **self as *mut [T]);
+            if self.cap != 0 && core::mem::size_of::<T>() != 0 {
+                let layout = Layout::array::<T>(self.cap).unwrap();
+                self.bump.dealloc(self.ptr.as_ptr() as *mut u8, layout);
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ArenaVec<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// A value in a bump allocator that gives its space back when dropped: the
+/// value is dropped and, if its block is still the top one, the cursor rolls
+/// back over it. Scoped, LIFO use (a scratch buffer per loop iteration, say)
+/// then doesn't grow the heap.
+pub struct Reclaim<'a, T> {
+    bump: &'a BumpAllocator,
+    ptr: NonNull<T>,
+}
+
+impl<'a, T> Reclaim<'a, T> {
+    /// Move `value` into `bump`, or return `None` if there's no room.
+    pub fn new_in(value: T, bump: &'a BumpAllocator) -> Option<Self> {
+        let ptr = bump.try_alloc(Layout::new::<T>()).ok()?.cast::<T>();
+        unsafe { ptr.as_ptr().write(value) };
+        Some(Reclaim { bump, ptr })
+    }
+}
+
+impl<T> core::ops::Deref for Reclaim<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> core::ops::DerefMut for Reclaim<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for Reclaim<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            core::ptr::drop_in_place(self.ptr.as_ptr());
+            self.bump
+                .dealloc(self.ptr.as_ptr() as *mut u8, Layout::new::<T>());
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Reclaim<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+// How much an affinity scope reserves up front.
+const AFFINITY_RESERVE: usize = 4096;
+
+/// A scope whose allocations are kept together; see
+/// `BumpAllocator::with_affinity`.
+pub struct Affinity<'a> {
+    bump: &'a BumpAllocator,
+    tag: &'static str,
+    // The current reservation: its address, how much of it is handed out,
+    // and its length.
+    start: Cell<usize>,
+    used: Cell<usize>,
+    len: Cell<usize>,
+}
+
+impl Affinity<'_> {
+    pub fn tag(&self) -> &'static str {
+        self.tag
+    }
+
+    /// Allocate `layout` inside the scope's reservation, growing or
+    /// replacing the reservation if it's full. Returns null if there's no
+    /// room anywhere.
+    pub fn alloc(&self, layout: Layout) -> *mut u8 {
+        loop {
+            let fit = checked_align_up(self.start.get() + self.used.get(), layout.align())
+                .map(|aligned| aligned - self.start.get())
+                .and_then(|offset| Some((offset, offset.checked_add(layout.size())?)));
+            if let Some((offset, end)) = fit {
+                if end <= self.len.get() && self.start.get() != 0 {
+                    self.used.set(end);
+                    return (self.start.get() + offset) as *mut u8;
+                }
+            }
+            let Some(needed) = layout.size().checked_add(layout.align()) else {
+                return null_mut();
+            };
+            // Double the reservation if it's still the top block...
+            let grown = self.len.get() + needed.max(self.len.get());
+            let start = self.start.get() as *mut u8;
+            if !start.is_null() && self.bump.grow_in_place(start, self.len.get(), grown) {
+                self.len.set(grown);
+                continue;
+            }
+            // ...or start a new one.
+            self.release_tail();
+            if !self.reserve(needed.max(AFFINITY_RESERVE)) && !self.reserve(needed) {
+                return null_mut();
+            }
+        }
+    }
+
+    fn reserve(&self, len: usize) -> bool {
+        let Ok(block) = Layout::from_size_align(len, 1) else {
+            return false;
+        };
+        match self.bump.try_alloc(block) {
+            Ok(ptr) => {
+                self.start.set(ptr.as_ptr() as usize);
+                self.used.set(0);
+                self.len.set(self.bump.block_size(len));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    // Free the unused end of the reservation (rolling the cursor back if it
+    // is still the top block).
+    fn release_tail(&self) {
+        let tail = self.len.get() - self.used.get();
+        if self.start.get() != 0 && tail != 0 {
+            let start = self.start.get() as *mut u8;
+            unsafe { self.bump.trim_block(start, self.len.get(), self.used.get()) };
+            self.len.set(self.used.get());
+        }
+    }
+}
+
+impl Drop for Affinity<'_> {
+    fn drop(&mut self) {
+        self.release_tail();
+    }
+}
+
+impl fmt::Debug for Affinity<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Affinity")
+            .field("tag", &self.tag)
+            .field("used", &self.used.get())
+            .field("reserved", &self.len.get())
+            .finish()
+    }
+}
+
+/// A run of same-lifetime allocations carved from one reservation; see
+/// `BumpAllocator::batch`.
+pub struct Batch<'a> {
+    bump: &'a BumpAllocator,
+    // The reservation (both 0 if there's none) and how far into it the
+    // batch has got.
+    start: usize,
+    end: usize,
+    cursor: Cell<usize>,
+}
+
+impl Batch<'_> {
+    /// Allocate `layout` from the reservation, or from the allocator itself
+    /// if it's used up. Returns null if there's no room either way.
+    pub fn alloc(&self, layout: Layout) -> *mut u8 {
+        let fit = checked_align_up(self.cursor.get(), layout.align())
+            .and_then(|aligned| Some((aligned, aligned.checked_add(layout.size())?)));
+        match fit {
+            Some((aligned, end)) if self.start != 0 && end <= self.end => {
+                self.cursor.set(end);
+                aligned as *mut u8
+            }
+            _ => self.bump.try_alloc(layout).map_or(null_mut(), NonNull::as_ptr),
+        }
+    }
+
+    /// Bytes of the reservation not handed out yet.
+    pub fn remaining(&self) -> usize {
+        self.end - self.cursor.get()
+    }
+}
+
+impl Drop for Batch<'_> {
+    fn drop(&mut self) {
+        // Free the unused end of the reservation, rolling the cursor back if
+        // nothing was allocated after it.
+        if self.start != 0 && self.remaining() != 0 {
+            let used = self.cursor.get() - self.start;
+            unsafe {
+                self.bump
+                    .trim_block(self.start as *mut u8, self.end - self.start, used)
+            };
+        }
+    }
+}
+
+impl fmt::Debug for Batch<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Batch")
+            .field("used", &(self.cursor.get() - self.start))
+            .field("reserved", &(self.end - self.start))
+            .finish()
+    }
+}
+
+/// How big a `ChunkedArena` makes its next chunk.
+#[derive(Clone, Copy, Debug)]
+pub enum GrowthPolicy {
+    /// Every chunk is this many bytes.
+    Fixed(usize),
+    /// Each new chunk takes the total capacity to this many times what it
+    /// was: 2.0 doubles the arena every time, and 1.0 or less adds only as
+    /// much as the allocation needs.
+    Geometric(f64),
+    /// Size the next chunk from the total capacity so far.
+    Custom(fn(usize) -> usize),
+}
+
+impl GrowthPolicy {
+    fn next_chunk(self, total: usize) -> usize {
+        match self {
+            GrowthPolicy::Fixed(size) => size,
+            GrowthPolicy::Geometric(factor) => (total as f64 * (factor - 1.0)) as usize,
+            GrowthPolicy::Custom(f) => f(total),
+        }
+    }
+}
+
+/// Where a `ChunkedArena` gets its chunks from, e.g. a pool of pre-faulted
+/// huge pages instead of the default `VecChunkSource`.
+///
+/// # Safety
+///
+/// A region returned by `acquire` must be valid for reads and writes, at
+/// least `min_size` bytes long, zero-filled, used by nothing else, and stay
+/// valid until it's passed back to `release`.
+pub unsafe trait ChunkSource {
+    /// A fresh region of at least `min_size` bytes, or `None` if there's none
+    /// to be had.
+    fn acquire(&mut self, min_size: usize) -> Option<NonNull<[u8]>>;
+
+    /// Take back a region handed out by `acquire`.
+    ///
+    /// # Safety
+    ///
+    /// `chunk` must have come from this source's `acquire`, exactly as
+    /// returned, and must not be used again.
+    unsafe fn release(&mut self, chunk: NonNull<[u8]>);
+}
+
+/// Chunks from the global allocator, as zeroed boxed slices.
+#[derive(Debug, Default)]
+pub struct VecChunkSource;
+
+unsafe impl ChunkSource for VecChunkSource {
+    fn acquire(&mut self, min_size: usize) -> Option<NonNull<[u8]>> {
+        let chunk = vec![0u8; min_size].into_boxed_slice();
+        NonNull::new(Box::into_raw(chunk))
+    }
+
+    unsafe fn release(&mut self, chunk: NonNull<[u8]>) {
+        drop(Box::from_raw(chunk.as_ptr()));
+    }
+}
+
+/// Chunks that are their own anonymous memory mappings, rounded up to whole
+/// pages, so they go straight back to the OS when released.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default)]
+pub struct MmapChunkSource;
+
+#[cfg(target_os = "linux")]
+unsafe impl ChunkSource for MmapChunkSource {
+    fn acquire(&mut self, min_size: usize) -> Option<NonNull<[u8]>> {
+        let page = unsafe { sys::sysconf(sys::SC_PAGESIZE) } as usize;
+        let len = min_size.checked_next_multiple_of(page)?.max(page);
+        let map = unsafe {
+            sys::mmap(
+                null_mut(),
+                len,
+                sys::PROT_READ | sys::PROT_WRITE,
+                sys::MAP_PRIVATE | sys::MAP_ANONYMOUS,
+                -1,
+            )
+        };
+        if map == sys::MAP_FAILED {
+            return None;
+        }
+        NonNull::new(core::ptr::slice_from_raw_parts_mut(map, len))
+    }
+
+    unsafe fn release(&mut self, chunk: NonNull<[u8]>) {
+        sys::munmap(chunk.as_ptr() as *mut u8, chunk.len());
+    }
+}
+
+/// An arena that never runs out while its `ChunkSource` has memory: when the
+/// current chunk is full it adds another, sized by its `GrowthPolicy`
+/// (doubling by default), instead of failing. Blocks never move, so pointers
+/// stay valid until `reset` or drop. Single-threaded.
+pub struct ChunkedArena<S: ChunkSource = VecChunkSource> {
+    // Each chunk is a whole region from `source`, handed back on drop.
+    chunks: RefCell<Vec<BumpAllocator>>,
+    // Index of the chunk allocations currently come from; chunks after it
+    // are empty, left over from before a `reset`.
+    current: Cell<usize>,
+    policy: GrowthPolicy,
+    source: RefCell<S>,
+    // Allocations that spilled past the chunk they were first tried in, and
+    // chunks added since the arena was created.
+    fallback_allocs: Cell<usize>,
+    grow_events: Cell<usize>,
+}
+
+// The chunks are only reachable through the arena, which owns them.
+unsafe impl<S: ChunkSource + Send> Send for ChunkedArena<S> {}
+
+impl ChunkedArena {
+    /// An arena starting with one `first_chunk`-byte chunk.
+    pub fn new(first_chunk: usize) -> Self {
+        Self::with_policy(first_chunk, GrowthPolicy::Geometric(2.0))
+    }
+
+    pub fn with_policy(first_chunk: usize, policy: GrowthPolicy) -> Self {
+        // A `Vec` never comes back empty-handed; it aborts instead.
+        Self::with_source(first_chunk, policy, VecChunkSource).unwrap()
+    }
+}
+
+impl<S: ChunkSource> ChunkedArena<S> {
+    /// An arena taking its chunks from `source`, starting with one of at
+    /// least `first_chunk` bytes. Returns `None` if `source` can't supply
+    /// that first chunk.
+    pub fn with_source(first_chunk: usize, policy: GrowthPolicy, mut source: S) -> Option<Self> {
+        let first = Self::chunk_from(&mut source, first_chunk)?;
+        Some(ChunkedArena {
+            chunks: RefCell::new(vec![first]),
+            current: Cell::new(0),
+            policy,
+            source: RefCell::new(source),
+            fallback_allocs: Cell::new(0),
+            grow_events: Cell::new(0),
+        })
+    }
+
+    fn chunk_from(source: &mut S, min_size: usize) -> Option<BumpAllocator> {
+        let region = source.acquire(min_size)?;
+        Some(BumpAllocator::for_region(region.as_ptr() as *mut u8 as usize, region.len()))
+    }
+
+    // The region a chunk was made from, to hand back to the source.
+    fn region_of(chunk: &BumpAllocator) -> NonNull<[u8]> {
+        let (start, end) = chunk.heap_range();
+        let region = core::ptr::slice_from_raw_parts_mut(start as *mut u8, end - start);
+        // Only ever built from a region `acquire` returned.
+        unsafe { NonNull::new_unchecked(region) }
+    }
+
+    pub fn set_growth_policy(&mut self, policy: GrowthPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.borrow().len()
+    }
+
+    /// Total size of all chunks.
+    pub fn capacity(&self) -> usize {
+        self.chunks.borrow().iter().map(|chunk| chunk.capacity()).sum()
+    }
+
+    pub fn bytes_used(&self) -> usize {
+        self.chunks.borrow().iter().map(|chunk| chunk.bytes_used()).sum()
+    }
+
+    /// The usage figures of all chunks added together, plus how often
+    /// allocations spilled into a later chunk and how many chunks were added.
+    pub fn stats(&self) -> BumpStats {
+        let mut total = BumpStats {
+            fallback_allocs: self.fallback_allocs.get(),
+            grow_events: self.grow_events.get(),
+            ..BumpStats::default()
+        };
+        for chunk in self.chunks.borrow().iter() {
+            total += chunk.stats();
+        }
+        total
+    }
+
+    /// `Arena::iter_bytes_chunked` for each chunk in turn: windows never span
+    /// two chunks, so each chunk's last one may be short. Panics if `chunk`
+    /// is 0.
+    pub fn iter_bytes_chunked(&self, chunk: usize) -> impl Iterator<Item = &[u8]> {
+        assert!(chunk != 0, "chunk size must be nonzero");
+        // Chunks never move, but the list of them can grow while this is
+        // being iterated, so take their used ranges up front.
+        let used: Vec<(usize, usize)> = self
+            .chunks
+            .borrow()
+            .iter()
+            .map(|bump| {
+                let start = bump.heap_start();
+                (start, bump.next.load(Ordering::Acquire) - start)
+            })
+            .collect();
+        used.into_iter().flat_map(move |(start, len)| {
+            unsafe { core::slice::from_raw_parts(start as *const u8, len) }.chunks(chunk)
+        })
+    }
+
+    /// Allocate `layout`, adding a chunk if it doesn't fit in the current
+    /// one. Returns null if the layout is invalid for any chunk size or the
+    /// source has no more chunks.
+    pub fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut chunks = self.chunks.borrow_mut();
+        let first = self.current.get();
+        loop {
+            let current = self.current.get();
+            if let Ok(ptr) = chunks[current].try_alloc(layout) {
+                if current != first {
+                    self.fallback_allocs.set(self.fallback_allocs.get() + 1);
+                }
+                return ptr.as_ptr();
+            }
+            if current + 1 == chunks.len() {
+                let total = chunks.iter().map(|chunk| chunk.capacity()).sum();
+                // Big enough for `layout` wherever the chunk's buffer lands.
+                let Some(needed) = layout.size().checked_add(layout.align()) else {
+                    return null_mut();
+                };
+                let size = self.policy.next_chunk(total).max(needed);
+                let Some(chunk) = Self::chunk_from(&mut self.source.borrow_mut(), size) else {
+                    return null_mut();
+                };
+                chunks.push(chunk);
+                self.grow_events.set(self.grow_events.get() + 1);
+            }
+            self.current.set(current + 1);
+        }
+    }
+
+    /// `BumpAllocator::alloc_fast` on the current chunk. It never moves on to
+    /// a later chunk or adds one, so once the current chunk is full it
+    /// returns null even though `alloc` would grow the arena.
+    pub fn alloc_fast(&self, layout: Layout) -> *mut u8 {
+        self.chunks.borrow()[self.current.get()].alloc_fast(layout)
+    }
+
+    /// Reclaim everything, keeping the chunks for reuse.
+    pub fn reset(&mut self) {
+        for chunk in self.chunks.get_mut() {
+            // `&mut self` means nothing handed out is still borrowed.
+            unsafe { chunk.reset() };
+        }
+        self.current.set(0);
+    }
+
+    /// Drop empty chunks from the end, handing them back to the source,
+    /// until the total capacity is at most `target`, to cap retained memory
+    /// after a spike. The first chunk and any chunk still in use are always
+    /// kept, so the result can stay above `target`. Returns the capacity
+    /// left.
+    pub fn shrink_chunks_to(&mut self, target: usize) -> usize {
+        let current = self.current.get();
+        let chunks = self.chunks.get_mut();
+        let mut total: usize = chunks.iter().map(|chunk| chunk.capacity()).sum();
+        while total > target && chunks.len() > (current + 1).max(1) {
+            match chunks.last() {
+                Some(last) if last.bytes_used() == 0 => {
+                    total -= last.capacity();
+                    let last = chunks.pop().unwrap();
+                    unsafe { self.source.get_mut().release(Self::region_of(&last)) };
+                }
+                _ => break,
+            }
+        }
+        total
+    }
+}
+
+impl<S: ChunkSource> Drop for ChunkedArena<S> {
+    fn drop(&mut self) {
+        let source = self.source.get_mut();
+        for chunk in self.chunks.get_mut().drain(..) {
+            unsafe { source.release(Self::region_of(&chunk)) };
+        }
+    }
+}
+
+impl<S: ChunkSource> fmt::Debug for ChunkedArena<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkedArena")
+            .field("chunks", &self.chunk_count())
+            .field("capacity", &self.capacity())
+            .field("bytes_used", &self.bytes_used())
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+/// A ring-buffer arena: when an allocation doesn't fit before the end of the
+/// buffer, it wraps around to the start and overwrites the oldest data. Good
+/// for short-lived, high-rate scratch allocations (per-frame data, log
+/// records) where nothing outlives a lap.
+pub struct RingArena {
+    // Only held to keep the region alive.
+    _buf: Vec<u8>,
+    base: usize,
+    capacity: usize,
+    // Whether `capacity` is a power of two so `% capacity` can be a mask.
+    masked: bool,
+    // Total bytes consumed since creation, including wrap and padding skips.
+    // The physical offset is this modulo `capacity`.
+    head: AtomicUsize,
+}
+
+impl RingArena {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_buffer(vec![0u8; capacity], false)
+    }
+
+    /// Like `new`, but rounds `capacity` down to a power of two so wrapping
+    /// is a bitmask instead of a division. Up to half of the requested bytes
+    /// (the tail past the power of two) go unused.
+    pub fn new_pow2(capacity: usize) -> Self {
+        let capacity = match capacity {
+            0 => 0,
+            n => 1 << (usize::BITS - 1 - n.leading_zeros()),
+        };
+        Self::with_buffer(vec![0u8; capacity], true)
+    }
+
+    fn with_buffer(mut buf: Vec<u8>, masked: bool) -> Self {
+        RingArena {
+            base: buf.as_mut_ptr() as usize,
+            capacity: buf.len(),
+            masked,
+            _buf: buf,
+            head: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Physical offset of the next allocation.
+    fn offset_of(&self, head: usize) -> usize {
+        if self.masked {
+            head & (self.capacity - 1)
+        } else {
+            head % self.capacity
+        }
+    }
+
+    /// Allocate `layout`, wrapping to the start of the buffer if it doesn't
+    /// fit before the end. Only fails (null) if the layout can never fit.
+    pub fn alloc(&self, layout: Layout) -> *mut u8 {
+        if self.capacity == 0 || layout.size() > self.capacity {
+            return null_mut();
+        }
+
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            let mut skip = 0;
+            let mut offset = self.offset_of(head);
+            let mut aligned = align_up(self.base + offset, layout.align());
+
+            // Doesn't fit before the end of the buffer: skip the tail and
+            // start the next lap from the beginning.
+            if aligned - self.base + layout.size() > self.capacity {
+                skip = self.capacity - offset;
+                offset = 0;
+                aligned = align_up(self.base, layout.align());
+                if aligned - self.base + layout.size() > self.capacity {
+                    return null_mut();
+                }
+            }
+
+            let consumed = skip + (aligned - self.base - offset) + layout.size();
+            match self.head.compare_exchange_weak(
+                head,
+                head + consumed,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return aligned as *mut u8,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+}
+
+/// A bump allocator over an `N`-byte buffer stored inline, so the whole heap
+/// can live on the stack or inside another value. It's single-threaded, and
+/// frees nothing until dropped.
+pub struct FixedBump<const N: usize> {
+    buf: UnsafeCell<[u8; N]>,
+    // Offset of the cursor into `buf`.
+    used: Cell<usize>,
+}
+
+const _: () = assert!(FixedBump::<256>::capacity() >= 128);
+
+impl<const N: usize> FixedBump<N> {
+    pub const fn new() -> Self {
+        FixedBump {
+            buf: UnsafeCell::new([0; N]),
+            used: Cell::new(0),
+        }
+    }
+
+    /// The buffer size, `N`, usable in const contexts.
+    pub const fn capacity() -> usize {
+        N
+    }
+
+    pub fn bytes_used(&self) -> usize {
+        self.used.get()
+    }
+
+    /// Allocate `layout` from the buffer, or return null if it doesn't fit.
+    /// Blocks are only valid while the `FixedBump` stays where it is.
+    pub fn alloc(&self, layout: Layout) -> *mut u8 {
+        let base = self.buf.get() as usize;
+        let Some(aligned) = (base + self.used.get()).checked_next_multiple_of(layout.align())
+        else {
+            return null_mut();
+        };
+        let offset = aligned - base;
+        match offset.checked_add(layout.size()) {
+            Some(end) if end <= N => {
+                self.used.set(end);
+                aligned as *mut u8
+            }
+            _ => null_mut(),
+        }
+    }
+}
+
+impl<const N: usize> Default for FixedBump<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The bits of the C library `MmapArena` needs. The constants come from
+// `libc`, since some of them differ between architectures; the functions
+// take and return `*mut u8` to save casts at every call.
+#[cfg(target_os = "linux")]
+mod sys {
+    pub use libc::{
+        sysconf, MAP_ANONYMOUS, MAP_PRIVATE, PROT_NONE, PROT_READ, PROT_WRITE,
+        _SC_PAGESIZE as SC_PAGESIZE,
+    };
+    #[cfg(not(feature = "single-threaded"))]
+    pub use libc::MAP_SHARED;
+    #[cfg(not(feature = "single-threaded"))]
+    use std::sync::atomic::AtomicU32;
+
+    pub const MAP_FAILED: *mut u8 = libc::MAP_FAILED as *mut u8;
+
+    pub unsafe fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: i32) -> *mut u8 {
+        libc::mmap(addr.cast(), len, prot, flags, fd, 0).cast()
+    }
+
+    pub unsafe fn munmap(addr: *mut u8, len: usize) -> i32 {
+        libc::munmap(addr.cast(), len)
+    }
+
+    pub unsafe fn mprotect(addr: *mut u8, len: usize, prot: i32) -> i32 {
+        libc::mprotect(addr.cast(), len, prot)
+    }
+
+    // Sleep until woken, as long as `word` still holds `expected`, for at
+    // most `timeout`. Without `FUTEX_PRIVATE_FLAG`, so that processes sharing
+    // the mapping can wake each other.
+    #[cfg(not(feature = "single-threaded"))]
+    pub fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<std::time::Duration>) {
+        let timeout = timeout.map(|timeout| libc::timespec {
+            tv_sec: timeout.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+            tv_nsec: timeout.subsec_nanos() as _,
+        });
+        let timeout = timeout.as_ref().map_or(core::ptr::null(), |t| t as *const _);
+        unsafe { libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAIT, expected, timeout) };
+    }
+
+    // Wake up to `count` of the threads sleeping on `word`, in any process.
+    #[cfg(not(feature = "single-threaded"))]
+    pub fn futex_wake(word: &AtomicU32, count: i32) {
+        unsafe { libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, count) };
+    }
+
+    #[cfg(not(feature = "single-threaded"))]
+    pub use libc::atexit;
+
+    #[cfg(not(feature = "single-threaded"))]
+    pub unsafe fn write(fd: i32, buf: *const u8, len: usize) -> isize {
+        libc::write(fd, buf.cast(), len)
+    }
+}
+
+/// An arena whose heap is its own anonymous memory mapping rather than a
+/// `Vec`, so the pages go straight back to the OS on drop and can have their
+/// protection changed. The page after the heap is a no-access guard page,
+/// so running off the end faults instead of scribbling over whatever is
+/// mapped next. The allocator's bookkeeping lives in a header at the start
+/// of the mapping, so with `new_shared` a process and the children it forks
+/// all allocate from (and wait on) the same arena. Only the `BumpAllocator`
+/// methods it wraps are offered, so every allocation goes through the check
+/// that lets strict-reset mode re-enable access, and every free wakes
+/// `alloc_blocking` callers.
+#[cfg(target_os = "linux")]
+pub struct MmapArena {
+    // The start of the mapping: the header, then the heap, then the guard
+    // page.
+    map: *mut u8,
+    header_len: usize,
+    heap_len: usize,
+    guard_len: usize,
+    // Whether `reset` should revoke all access to the heap (strict mode),
+    // and whether it currently is revoked. Page protection is per process,
+    // so these are too.
+    #[cfg(feature = "strict-reset")]
+    strict: AtomicBool,
+    #[cfg(feature = "strict-reset")]
+    protected: AtomicBool,
+}
+
+// What an `MmapArena`'s mapping starts with.
+#[cfg(target_os = "linux")]
+struct MmapHeader {
+    bump: BumpAllocator,
+    // Bumped every time something makes room; `alloc_blocking` callers
+    // sleep on it as a futex until it changes.
+    #[cfg(not(feature = "single-threaded"))]
+    space: AtomicU32,
+    // How many callers are asleep on `space`, so frees can skip the wake
+    // when there are none.
+    #[cfg(not(feature = "single-threaded"))]
+    sleepers: AtomicU32,
+    // A futex lock (0 free, 1 held, 2 held with sleepers) held for each
+    // `alloc_blocking` attempt and for each `reset`, so a reset can't land in
+    // the middle of an attempt.
+    #[cfg(not(feature = "single-threaded"))]
+    attempt: AtomicU32,
+}
+
+#[cfg(target_os = "linux")]
+unsafe impl Send for MmapArena {}
+#[cfg(all(target_os = "linux", not(feature = "single-threaded")))]
+unsafe impl Sync for MmapArena {}
+
+#[cfg(target_os = "linux")]
+impl MmapArena {
+    /// Map a heap of at least `capacity` bytes (rounded up to whole pages),
+    /// private to this process. Returns `None` if the mapping fails.
+    pub fn new(capacity: usize) -> Option<Self> {
+        Self::map(capacity, sys::MAP_PRIVATE)
+    }
+
+    /// `new`, but with the mapping shared with child processes forked after
+    /// it's made: parent and children all allocate from, free into and wait
+    /// on the one arena, and a pointer it hands out means the same in each.
+    /// The free list and tracking mode take process-private locks, so leave
+    /// them off if more than one process uses the arena.
+    #[cfg(not(feature = "single-threaded"))]
+    pub fn new_shared(capacity: usize) -> Option<Self> {
+        Self::map(capacity, sys::MAP_SHARED)
+    }
+
+    fn map(capacity: usize, sharing: i32) -> Option<Self> {
+        let page = unsafe { sys::sysconf(sys::SC_PAGESIZE) } as usize;
+        let header_len = core::mem::size_of::<MmapHeader>().next_multiple_of(page);
+        let heap_len = capacity.checked_next_multiple_of(page)?.max(page);
+        let map_len = header_len.checked_add(heap_len)?.checked_add(page)?;
+        let map = unsafe {
+            sys::mmap(
+                null_mut(),
+                map_len,
+                sys::PROT_READ | sys::PROT_WRITE,
+                sharing | sys::MAP_ANONYMOUS,
+                -1,
+            )
+        };
+        if map == sys::MAP_FAILED {
+            return None;
+        }
+        unsafe {
+            if sys::mprotect(map.add(header_len + heap_len), page, sys::PROT_NONE) != 0 {
+                sys::munmap(map, map_len);
+                return None;
+            }
+            // Mappings are page-aligned, which is plenty for the header.
+            map.cast::<MmapHeader>().write(MmapHeader {
+                bump: BumpAllocator::for_region(map as usize + header_len, heap_len),
+                #[cfg(not(feature = "single-threaded"))]
+                space: AtomicU32::new(0),
+                #[cfg(not(feature = "single-threaded"))]
+                sleepers: AtomicU32::new(0),
+                #[cfg(not(feature = "single-threaded"))]
+                attempt: AtomicU32::new(0),
+            });
+        }
+        Some(MmapArena {
+            map,
+            header_len,
+            heap_len,
+            guard_len: page,
+            #[cfg(feature = "strict-reset")]
+            strict: AtomicBool::new(false),
+            #[cfg(feature = "strict-reset")]
+            protected: AtomicBool::new(false),
+        })
+    }
+
+    fn header(&self) -> &MmapHeader {
+        // Written by `map`, and only dropped along with `self`.
+        unsafe { &*self.map.cast::<MmapHeader>() }
+    }
+
+    fn bump(&self) -> &BumpAllocator {
+        &self.header().bump
+    }
+
+    /// Allocate `layout` from the mapping, or return null if it doesn't fit.
+    pub fn alloc(&self, layout: Layout) -> *mut u8 {
+        #[cfg(feature = "strict-reset")]
+        self.unprotect();
+        self.bump().try_alloc(layout).map_or(null_mut(), NonNull::as_ptr)
+    }
+
+    // Make the heap accessible again after a strict `reset`. The flag is
+    // only cleared once `mprotect` is done, so a racing `alloc` that sees it
+    // clear can use the heap straight away, and one that still sees it set
+    // just unprotects a second time.
+    #[cfg(feature = "strict-reset")]
+    fn unprotect(&self) {
+        if self.protected.load(Ordering::Acquire) {
+            let heap = unsafe { self.map.add(self.header_len) };
+            unsafe { sys::mprotect(heap, self.heap_len, sys::PROT_READ | sys::PROT_WRITE) };
+            self.protected.store(false, Ordering::Release);
+        }
+    }
+
+    /// `alloc`, but if the heap is full, wait for room instead of failing,
+    /// so the arena can back a bounded queue. Another thread, or another
+    /// process sharing a `new_shared` arena, makes room with `dealloc`,
+    /// `rewind` or `reset`, each of which wakes every waiter. Returns null if
+    /// there's still no room once `timeout` has passed, or if `layout` could
+    /// never fit. The OOM handler runs in the middle of an attempt, so it
+    /// mustn't `reset` the arena.
+    #[cfg(not(feature = "single-threaded"))]
+    pub fn alloc_blocking(&self, layout: Layout, timeout: std::time::Duration) -> *mut u8 {
+        let header = self.header();
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            // Read before trying, so that room made after the failed try
+            // shows up as a change and the wait returns straight away.
+            let seen = header.space.load(Ordering::SeqCst);
+            let ptr = {
+                let _attempt = self.lock_attempts();
+                self.alloc(layout)
+            };
+            if !ptr.is_null() || layout.size() > self.usable_capacity() {
+                return ptr;
+            }
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            if left.is_zero() {
+                return null_mut();
+            }
+            header.sleepers.fetch_add(1, Ordering::SeqCst);
+            sys::futex_wait(&header.space, seen, Some(left));
+            header.sleepers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    // Wake every `alloc_blocking` caller, in any process, once something has
+    // made room.
+    #[cfg(not(feature = "single-threaded"))]
+    fn space_freed(&self) {
+        let header = self.header();
+        header.space.fetch_add(1, Ordering::SeqCst);
+        if header.sleepers.load(Ordering::SeqCst) != 0 {
+            sys::futex_wake(&header.space, i32::MAX);
+        }
+    }
+
+    // Take the header's `attempt` lock until the guard is dropped.
+    #[cfg(not(feature = "single-threaded"))]
+    fn lock_attempts(&self) -> AttemptGuard<'_> {
+        let lock = &self.header().attempt;
+        if lock
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while lock.swap(2, Ordering::Acquire) != 0 {
+                sys::futex_wait(lock, 2, None);
+            }
+        }
+        AttemptGuard(lock)
+    }
+
+    /// In strict mode, `reset` makes the whole heap inaccessible until the
+    /// next `alloc`, so touching a stale pointer faults on the spot instead
+    /// of quietly reading reused memory. Only this process's view of the
+    /// heap is protected.
+    #[cfg(feature = "strict-reset")]
+    pub fn set_strict_reset(&self, enabled: bool) {
+        self.strict.store(enabled, Ordering::Relaxed);
+    }
+
+    /// `BumpAllocator::reset`, revoking access to the heap in strict mode
+    /// and waking any `alloc_blocking` callers.
+    ///
+    /// # Safety
+    ///
+    /// Same as `BumpAllocator::reset`, counting allocations in every process
+    /// sharing the arena, except that callers of `alloc_blocking` may keep
+    /// waiting (or trying) through it.
+    pub unsafe fn reset(&self) {
+        {
+            #[cfg(not(feature = "single-threaded"))]
+            let _attempt = self.lock_attempts();
+            self.bump().reset();
+            #[cfg(feature = "strict-reset")]
+            if self.strict.load(Ordering::Relaxed) {
+                sys::mprotect(self.map.add(self.header_len), self.heap_len, sys::PROT_NONE);
+                self.protected.store(true, Ordering::Release);
+            }
+        }
+        #[cfg(not(feature = "single-threaded"))]
+        self.space_freed();
+    }
+
+    /// `BumpAllocator`'s `GlobalAlloc::dealloc`, waking any `alloc_blocking`
+    /// callers.
+    ///
+    /// # Safety
+    ///
+    /// Same as `GlobalAlloc::dealloc`.
+    pub unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.bump().dealloc(ptr, layout);
+        #[cfg(not(feature = "single-threaded"))]
+        self.space_freed();
+    }
+
+    pub fn mark(&self) -> Marker {
+        self.bump().mark()
+    }
+
+    /// `BumpAllocator::rewind`, waking any `alloc_blocking` callers.
+    ///
+    /// # Safety
+    ///
+    /// Same as `BumpAllocator::rewind`.
+    pub unsafe fn rewind(&self, marker: Marker) {
+        self.bump().rewind(marker);
+        #[cfg(not(feature = "single-threaded"))]
+        self.space_freed();
+    }
+
+    /// Size of the mapping, less the guard page: the header, then the heap.
+    pub fn capacity(&self) -> usize {
+        self.header_len + self.bump().capacity()
+    }
+
+    /// Size of the heap alone, i.e. `capacity` less the header: at least
+    /// what was asked for in `new`.
+    pub fn usable_capacity(&self) -> usize {
+        self.bump().usable_capacity()
+    }
+
+    pub fn bytes_used(&self) -> usize {
+        self.bump().bytes_used()
+    }
+
+    pub fn heap_range(&self) -> (usize, usize) {
+        self.bump().heap_range()
+    }
+
+    pub fn stats(&self) -> BumpStats {
+        self.bump().stats()
+    }
+}
+
+// Releases `MmapArena`'s attempt lock, waking a sleeper if there was one.
+#[cfg(all(target_os = "linux", not(feature = "single-threaded")))]
+struct AttemptGuard<'a>(&'a AtomicU32);
+
+#[cfg(all(target_os = "linux", not(feature = "single-threaded")))]
+impl Drop for AttemptGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.swap(0, Ordering::Release) == 2 {
+            sys::futex_wake(self.0, 1);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for MmapArena {
+    fn drop(&mut self) {
+        unsafe {
+            core::ptr::drop_in_place(self.map.cast::<MmapHeader>());
+            sys::munmap(self.map, self.header_len + self.heap_len + self.guard_len);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl fmt::Debug for MmapArena {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmapArena")
+            .field("name", &self.bump().name)
+            .field("capacity", &self.capacity())
+            .field("bytes_used", &self.bytes_used())
+            .finish()
+    }
+}
+
+// 4. Create a static instance of our BumpAllocator and tag it as the global allocator.
+// (A `single-threaded` build's allocator isn't `Sync`, so can't be one.)
+// With the `grow-down` feature it fills `HEAP` from the top, so heap
+// addresses decrease over time, which makes some stack/heap mix-ups stand out.
+// The test harness keeps the system allocator: it allocates far more than
+// `HEAP` holds, panic messages included. So do builds with the
+// `system-allocator` feature, such as the benchmarks, which include this file.
+#[cfg(not(feature = "single-threaded"))]
+#[cfg_attr(not(any(test, feature = "system-allocator")), global_allocator)]
+static GLOBAL: BumpAllocator =
+    unsafe { BumpAllocator::with_lazy_zeroed_region(heap_region) }.with_direction(GLOBAL_DIRECTION);
+
+#[cfg(not(any(feature = "single-threaded", feature = "grow-down")))]
+const GLOBAL_DIRECTION: Direction = Direction::Up;
+#[cfg(all(not(feature = "single-threaded"), feature = "grow-down"))]
+const GLOBAL_DIRECTION: Direction = Direction::Down;
+
+// 5. The heap addresses have to be known before the first allocation, and the
+// standard library allocates before `main` even runs, so a manual "init" call
+// at the top of `main` would be too late. Instead the allocator asks for its
+// region the first time it's used.
+#[cfg(not(feature = "single-threaded"))]
+fn heap_region() -> &'static mut [u8] {
+    // Only ever called once, by `GLOBAL`, so this is the sole reference. It's
+    // a zero-initialized static, as `with_lazy_zeroed_region` needs.
+    unsafe { &mut *core::ptr::addr_of_mut!(HEAP) }
+}
+
+// 6. Setting `BUMP_TRACE=1` dumps the global allocator's summary to stderr
+// at exit. The summary is formatted into a stack buffer (cut short if it's
+// too long) rather than anything heap-backed, since it runs during teardown.
+#[cfg(all(target_os = "linux", not(feature = "single-threaded")))]
+fn trace_at_exit_if_requested() {
+    extern "C" fn dump() {
+        struct StackBuf {
+            buf: [u8; 4096],
+            len: usize,
+        }
+        impl fmt::Write for StackBuf {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                let n = s.len().min(self.buf.len() - self.len);
+                self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+                self.len += n;
+                Ok(())
+            }
+        }
+
+        let mut out = StackBuf {
+            buf: [0; 4096],
+            len: 0,
+        };
+        let _ = GLOBAL.write_summary(&mut out);
+        unsafe { sys::write(2, out.buf.as_ptr(), out.len) };
+    }
+
+    if std::env::var_os("BUMP_TRACE").is_some_and(|value| value == "1") {
+        unsafe { sys::atexit(dump) };
+    }
+}
+
+fn main() {
+    #[cfg(all(target_os = "linux", not(feature = "single-threaded")))]
+    trace_at_exit_if_requested();
+
+    // **DEMO A**: Allocate a Box on our custom "heap"
+    // The memory used by this Box will come from our BumpAllocator, not the default system malloc.
+    let my_box = Box::new(42);
+    println!("Allocated a box with value: {}", my_box);
+
+    // **DEMO B**: Allocate a vector
+    // This will also use our custom allocator.
+    let mut my_vec = Vec::with_capacity(10);
+    my_vec.extend_from_slice(&[1, 2, 3, 4, 5]);
+    println!("Allocated a vector: {:?}", my_vec);
+
+    // **DEMO C**: Manual "malloc" style usage with pointer arithmetic
+    // We'll do a small example to store some data using unsafe pointers.
+    #[cfg(not(feature = "single-threaded"))]
+    let bump: &BumpAllocator = &GLOBAL;
+    // Without a global bump allocator, use a standalone arena instead.
+    #[cfg(feature = "single-threaded")]
+    let arena = Arena::new(HEAP_SIZE);
+    #[cfg(feature = "single-threaded")]
+    let bump: &BumpAllocator = &arena;
+    unsafe {
+        let size = 8; // let's say we want 8 bytes
+        let align = 4; // alignment requirement
+        let layout = checked_layout(size, align).unwrap();
+
+        // Our naive 'malloc' call
+        let ptr = bump.alloc(layout);
+        if !ptr.is_null() {
+            // Store data: We'll store two 32-bit integers in that block
+            // `ptr` is of type *mut u8; let's cast it to a *mut u32 for storing an integer
+            let int_ptr = ptr as *mut u32;
+            *int_ptr = 0xDEAD_BEEF;      // store first integer
+            *int_ptr.add(1) = 0xC0FFEE;  // store second integer
+
+            // Read back the data
+            println!(
+                "Manually allocated block. First: 0x{:X}, Second: 0x{:X}",
+                *int_ptr,
+                *int_ptr.add(1)
+            );
+        } else {
+            eprintln!("Out of memory in manual allocation!");
+        }
+    }
+
+    // Note: We are not calling `dealloc` here since our naive bump allocator
+    // effectively doesn't handle real frees. The memory usage only grows upward.
+    // Everything is freed once the process ends.
+
+    println!("Demo complete.");
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::AssertUnwindSafe;
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn bytes_wasted_counts_padding_and_dead_blocks() {
+        let arena = Arena::new(1024).align_base_to(8);
+        unsafe {
+            arena.bump.alloc(layout(1, 1));
+            let middle = arena.bump.alloc(layout(8, 8));
+            arena.bump.alloc(layout(8, 8));
+            arena.bump.dealloc(middle, layout(8, 8));
+        }
+        // 7 bytes of padding after the first block, plus the freed middle one.
+        assert_eq!(arena.bytes_wasted(), 7 + 8);
+    }
+
+    #[test]
+    fn compact_to_packs_live_handles() {
+        let arena = Arena::new(4096);
+        let mut handles: Vec<Handle> = (0..4u8)
+            .map(|i| {
+                let handle = arena.alloc_handle(layout(16, 8)).unwrap();
+                unsafe { core::ptr::write_bytes(arena.resolve(&handle).unwrap(), i + 1, 16) };
+                handle
+            })
+            .collect();
+        arena.free_handle(&mut handles[0]);
+        arena.free_handle(&mut handles[2]);
+
+        let mut dest = Arena::new(4096);
+        assert!(arena.compact_to(&mut dest, &mut handles));
+        assert_eq!(dest.bytes_used(), 32);
+        assert!(dest.bytes_used() < arena.bytes_used());
+        assert!(!handles[0].is_live());
+        unsafe {
+            let second = core::slice::from_raw_parts(dest.resolve(&handles[1]).unwrap(), 16);
+            let fourth = core::slice::from_raw_parts(dest.resolve(&handles[3]).unwrap(), 16);
+            assert!(second.iter().all(|&b| b == 2));
+            assert!(fourth.iter().all(|&b| b == 4));
+        }
+    }
+
+    #[test]
+    fn failed_compact_to_leaves_dest_as_it_was() {
+        let arena = Arena::new(4096);
+        let mut handles: Vec<Handle> = (0..3)
+            .map(|_| arena.alloc_handle(layout(64, 8)).unwrap())
+            .collect();
+        let before = handles[0].offset();
+
+        let mut dest = Arena::new(192);
+        dest.enable_free_list();
+        let reused = dest.alloc_handle(layout(64, 8)).unwrap();
+        dest.alloc_handle(layout(8, 8)).unwrap();
+        let mut freed = reused;
+        dest.free_handle(&mut freed);
+        let used = dest.bytes_used();
+
+        // The first copy reuses the freed block, the second is bumped, and
+        // the third doesn't fit.
+        assert!(!arena.compact_to(&mut dest, &mut handles));
+        assert_eq!(dest.bytes_used(), used);
+        assert_eq!(handles[0].offset(), before);
+        assert!(dest.alloc_handle(layout(64, 8)).is_some());
+    }
+
+    #[test]
+    fn alloc_zeroed_clears_a_reused_block() {
+        let arena = Arena::new(4096);
+        unsafe {
+            arena.bump.alloc(layout(64, 16));
+            let old = arena.bump.alloc(layout(64, 16));
+            core::ptr::write_bytes(old, 7, 64);
+            arena.bump.dealloc(old, layout(64, 16));
+
+            // Half of the new block is the freed one, half never handed out.
+            let new = arena.bump.alloc_zeroed(layout(128, 16));
+            assert_eq!(new, old);
+            assert!(core::slice::from_raw_parts(new, 128)
+                .iter()
+                .all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn alloc_zeroed_clears_a_caller_region() {
+        let mut buf = vec![0xFFu64; 32];
+        let bump = BumpAllocator::new_uninitialized();
+        unsafe {
+            bump.init(buf.as_mut_ptr() as usize, 256);
+            let ptr = bump.alloc_zeroed(layout(16, 8));
+            assert!(core::slice::from_raw_parts(ptr, 16).iter().all(|&b| b == 0));
+        }
+
+        let lazy =
+            BumpAllocator::with_lazy_region(|| Box::leak(vec![0xFF; 256].into_boxed_slice()));
+        let ptr = lazy.try_alloc_zeroed(layout(16, 8)).unwrap();
+        let block = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), 16) };
+        assert!(block.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn alloc_in_range_stays_in_its_window() {
+        let arena = Arena::new(4096).align_base_to(16);
+        let start = arena.heap_start();
+        let low = arena.alloc_in_range(layout(64, 16), 0, 1024) as usize;
+        assert!(low >= start && low + 64 <= start + 1024);
+
+        // A window past the cursor leaves a gap, counted as waste.
+        let high = arena.alloc_in_range(layout(64, 16), 2048, 3000) as usize;
+        assert!(high >= start + 2048 && high + 64 <= start + 3000);
+        assert_eq!(arena.bytes_wasted(), 2048 - 64);
+
+        // The cursor is now past the first window.
+        assert!(arena.alloc_in_range(layout(64, 16), 0, 1024).is_null());
+    }
+
+    #[test]
+    fn clear_and_zero_resets_and_wipes() {
+        let arena = Arena::new(4096);
+        unsafe {
+            let first = arena.bump.alloc(layout(64, 16));
+            core::ptr::write_bytes(first, 7, 64);
+            let second = arena.bump.alloc(layout(64, 16));
+            core::ptr::write_bytes(second, 9, 64);
+            let end = second as usize + 64;
+
+            arena.clear_and_zero();
+            assert_eq!(arena.bytes_used(), 0);
+            let used = core::slice::from_raw_parts(first, end - first as usize);
+            assert!(used.iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn pow2_ring_wraps_with_the_mask() {
+        let ring = RingArena::new_pow2(1000);
+        assert_eq!(ring.capacity(), 512);
+        let offsets: Vec<usize> = (0..7)
+            .map(|_| ring.alloc(layout(100, 1)) as usize - ring.base)
+            .collect();
+        // The sixth block doesn't fit before the end, so it starts the second
+        // lap; the seventh is found by masking a head that's past 512.
+        assert_eq!(offsets, [0, 100, 200, 300, 400, 0, 100]);
+    }
+
+    #[test]
+    fn stat_reads_agree_across_orderings() {
+        let arena = Arena::new(4096);
+        unsafe {
+            arena.bump.alloc(layout(64, 16));
+            arena.bump.alloc(layout(64, 16));
+        }
+        assert_eq!(arena.bytes_used_with(Ordering::Relaxed), arena.bytes_used());
+        assert_eq!(arena.bytes_used_with(Ordering::SeqCst), arena.bytes_used());
+        assert_eq!(arena.peak_with(Ordering::Relaxed), arena.peak());
+        assert_eq!(arena.alloc_count_with(Ordering::Relaxed), 2);
+        assert_eq!(arena.alloc_count_with(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn try_reserve_reports_the_shortfall() {
+        let arena = Arena::new(100);
+        unsafe { arena.bump.alloc(layout(40, 1)) };
+        assert_eq!(arena.try_reserve(70), Err(60));
+        assert_eq!(arena.try_reserve(10), Ok(50));
+    }
+
+    #[test]
+    #[should_panic(expected = "leaked 8 bytes")]
+    fn assert_no_leaks_catches_a_leak() {
+        let arena = Arena::new(100);
+        unsafe { arena.bump.alloc(layout(8, 1)) };
+        arena.assert_no_leaks();
+    }
+
+    #[test]
+    #[should_panic(expected = "still live: 8 bytes at offset 0, 16 bytes at offset 8")]
+    fn assert_no_leaks_lists_the_live_allocations() {
+        let mut arena = Arena::new(100).align_base_to(8);
+        arena.enable_tracking(8);
+        unsafe {
+            arena.bump.alloc(layout(8, 1));
+            arena.bump.alloc(layout(16, 8));
+            let freed = arena.bump.alloc(layout(4, 4));
+            arena.bump.dealloc(freed, layout(4, 4));
+        }
+        arena.assert_no_leaks();
+    }
+
+    #[test]
+    fn assert_no_leaks_passes_after_reset() {
+        let arena = Arena::new(100);
+        unsafe {
+            arena.bump.alloc(layout(8, 1));
+            arena.reset();
+        }
+        arena.assert_no_leaks();
+    }
+
+    #[test]
+    fn alignment_bigger_than_the_heap_fails_cleanly() {
+        let arena = Arena::new(1 << 20);
+        let huge_align = layout(8, 2 << 20);
+        assert_eq!(arena.try_alloc(huge_align), Err(AllocError::InvalidAlign));
+        assert!(unsafe { arena.bump.alloc(huge_align) }.is_null());
+        assert_eq!(
+            arena.try_alloc(layout(2 << 20, 8)),
+            Err(AllocError::OutOfMemory)
+        );
+        assert_eq!(arena.bytes_used(), 0);
+    }
+
+    #[test]
+    fn adjacent_free_blocks_merge() {
+        let arena = Arena::new(4096);
+        arena.enable_free_list();
+        unsafe {
+            let first = arena.bump.alloc(layout(32, 8));
+            let second = arena.bump.alloc(layout(32, 8));
+            arena.bump.alloc(layout(32, 8));
+            arena.bump.dealloc(first, layout(32, 8));
+            arena.bump.dealloc(second, layout(32, 8));
+            assert_eq!(arena.bytes_wasted(), 64);
+
+            // Neither block alone holds 64 bytes; merged, they do.
+            let merged = arena.bump.alloc(layout(64, 8));
+            assert_eq!(merged, first);
+            assert_eq!(arena.bytes_used(), 96);
+            assert_eq!(arena.bytes_wasted(), 0);
+        }
+    }
+
+    #[test]
+    fn freeing_the_top_block_reclaims_free_ones_below() {
+        let arena = Arena::new(4096);
+        arena.enable_free_list();
+        unsafe {
+            let first = arena.bump.alloc(layout(32, 8));
+            let second = arena.bump.alloc(layout(32, 8));
+            arena.bump.dealloc(first, layout(32, 8));
+            arena.bump.dealloc(second, layout(32, 8));
+        }
+        assert_eq!(arena.bytes_used(), 0);
+        assert_eq!(arena.bytes_wasted(), 0);
+        arena.assert_no_leaks();
+    }
+
+    #[cfg(not(feature = "single-threaded"))]
+    #[test]
+    fn bytes_used_stays_sane_while_the_tail_is_reclaimed() {
+        let arena = Arena::new(4096);
+        arena.enable_free_list();
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    assert!(arena.bytes_used() <= arena.capacity());
+                }
+            });
+            for _ in 0..20_000 {
+                unsafe {
+                    let below = arena.bump.alloc(layout(96, 8));
+                    let top = arena.bump.alloc(layout(96, 8));
+                    arena.bump.dealloc(below, layout(96, 8));
+                    arena.bump.dealloc(top, layout(96, 8));
+                }
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(arena.bytes_used(), 0);
+    }
+
+    #[test]
+    fn out_of_band_free_list_survives_an_overrun() {
+        let mut arena = Arena::new(4096);
+        arena.enable_free_list_out_of_band(8);
+        unsafe {
+            let first = arena.bump.alloc(layout(32, 8));
+            let second = arena.bump.alloc(layout(32, 8));
+            let third = arena.bump.alloc(layout(32, 8));
+            arena.bump.alloc(layout(32, 8));
+            arena.bump.dealloc(second, layout(32, 8));
+            // Run 8 bytes past the first block, into the freed second one,
+            // where an inline header would have been.
+            core::ptr::write_bytes(first, 0xFF, 40);
+            arena.bump.dealloc(third, layout(32, 8));
+            assert_eq!(arena.bytes_wasted(), 64);
+
+            assert_eq!(arena.bump.alloc(layout(64, 8)), second);
+            assert_eq!(arena.bytes_wasted(), 0);
+        }
+    }
+
+    #[test]
+    fn reset_stats_keeps_live_data() {
+        let arena = Arena::new(4096);
+        unsafe {
+            let kept = arena.bump.alloc(Layout::new::<u64>()) as *mut u64;
+            *kept = 5;
+            arena.bump.alloc(Layout::new::<u64>());
+            arena.reset_stats();
+            arena.bump.alloc(Layout::new::<u64>());
+            assert_eq!(arena.alloc_count(), 1);
+            assert_eq!(*kept, 5);
+        }
+        assert_eq!(arena.bytes_used(), 24);
+    }
+
+    #[test]
+    fn alloc_with_offset_align_aligns_the_field() {
+        let arena = Arena::new(4096);
+        unsafe { arena.bump.alloc(layout(3, 1)) };
+        let ptr = arena.alloc_with_offset_align(layout(32, 8), 8, 64) as usize;
+        assert_ne!(ptr, 0);
+        assert_eq!(ptr % 8, 0);
+        assert_eq!((ptr + 8) % 64, 0);
+        // An 8-aligned block can't put offset 4 on a 64-byte boundary.
+        assert!(arena
+            .alloc_with_offset_align(layout(32, 8), 4, 64)
+            .is_null());
+    }
+
+    #[test]
+    fn uninitialized_allocator_is_empty_until_init() {
+        let bump = const { BumpAllocator::new_uninitialized() };
+        assert!(bump.is_empty());
+        assert_eq!(bump.capacity(), 0);
+        assert!(unsafe { bump.alloc(Layout::new::<u8>()) }.is_null());
+
+        let mut buf = vec![0u8; 64];
+        unsafe { bump.init(buf.as_mut_ptr() as usize, buf.len()) };
+        assert_eq!(bump.capacity(), 64);
+        assert!(!unsafe { bump.alloc(Layout::new::<u8>()) }.is_null());
+    }
+
+    #[test]
+    fn lazy_region_is_set_up_once_on_first_use() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn region() -> &'static mut [u8] {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            Box::leak(vec![0u8; 256].into_boxed_slice())
+        }
+        let bump = const { BumpAllocator::with_lazy_region(region) };
+
+        assert_eq!(CALLS.load(Ordering::SeqCst), 0);
+        unsafe {
+            assert!(!bump.alloc(Layout::new::<u64>()).is_null());
+            assert!(!bump.alloc(Layout::new::<u64>()).is_null());
+        }
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+        assert_eq!(bump.capacity(), 256);
+    }
+
+    #[test]
+    fn can_fit_accounts_for_alignment() {
+        let arena = Arena::new(64).align_base_to(16);
+        unsafe { arena.bump.alloc(layout(49, 1)) };
+        // 15 bytes are left, but a 16-aligned block would start at 64.
+        assert!(arena.try_reserve(8).is_ok());
+        assert!(!arena.can_fit(layout(8, 16)));
+        assert!(arena.can_fit(layout(8, 1)));
+    }
+
+    #[test]
+    fn sampled_stats_are_scaled_back_up() {
+        let arena = Arena::new(8192);
+        arena.set_stats_sampling(10);
+        for _ in 0..100 {
+            unsafe { arena.bump.alloc(layout(8, 8)) };
+        }
+        assert_eq!(arena.alloc_count(), 100);
+        assert_eq!(arena.size_histogram()[3], 100);
+    }
+
+    #[test]
+    fn handles_go_stale_on_reset() {
+        let arena = Arena::new(256);
+        let handle = arena.alloc_handle(Layout::new::<u32>()).unwrap();
+        assert!(arena.resolve(&handle).is_some());
+        unsafe { arena.reset() };
+        assert!(arena.is_stale(&handle));
+        assert!(arena.resolve(&handle).is_none());
+    }
+
+    #[test]
+    fn grow_top_from_extends_in_place() {
+        let arena = Arena::new(1024);
+        unsafe {
+            let mut buf = arena.bump.alloc(layout(4, 1));
+            core::ptr::copy_nonoverlapping(b"abcd".as_ptr(), buf, 4);
+            let mut len = 4;
+            for digit in b'0'..=b'9' {
+                let grown = arena.grow_top_from(buf, len, 1).unwrap();
+                assert_eq!(grown, buf);
+                *grown.add(len) = digit;
+                len += 1;
+                buf = grown;
+            }
+            assert_eq!(core::slice::from_raw_parts(buf, len), b"abcd0123456789");
+
+            // Once something sits above it, growing has to move it.
+            arena.bump.alloc(Layout::new::<u8>());
+            let moved = arena.grow_top_from(buf, len, 2).unwrap();
+            assert_ne!(moved, buf);
+            assert_eq!(core::slice::from_raw_parts(moved, len), b"abcd0123456789");
+        }
+    }
+
+    #[test]
+    fn oom_report_says_when_a_request_exceeds_the_heap() {
+        static LAST: Mutex<String> = Mutex::new(String::new());
+        fn record(report: &OomReport) {
+            *LAST.lock().unwrap() = report.to_string();
+        }
+        let arena = Arena::new(256);
+        arena.set_oom_handler(record);
+
+        arena.try_alloc(layout(1000, 1)).unwrap_err();
+        let too_big = LAST.lock().unwrap().clone();
+        unsafe { arena.bump.alloc(layout(200, 1)) };
+        arena.try_alloc(layout(100, 1)).unwrap_err();
+        let full = LAST.lock().unwrap().clone();
+
+        #[cfg(feature = "warn-on-large")]
+        assert!(too_big.starts_with("request exceeds total heap: 1000 bytes"));
+        #[cfg(not(feature = "warn-on-large"))]
+        assert!(too_big.starts_with("failed to allocate 1000 bytes"));
+        assert!(full.starts_with("failed to allocate 100 bytes"));
+    }
+
+    // Claims 5 items but yields 19.
+    struct TooLong(u32);
+
+    impl Iterator for TooLong {
+        type Item = u32;
+
+        fn next(&mut self) -> Option<u32> {
+            self.0 += 1;
+            (self.0 < 20).then_some(self.0)
+        }
+    }
+
+    impl ExactSizeIterator for TooLong {
+        fn len(&self) -> usize {
+            5
+        }
+    }
+
+    // Claims 5 items but yields 2, each owning heap memory.
+    struct TooShort(u32);
+
+    impl Iterator for TooShort {
+        type Item = String;
+
+        fn next(&mut self) -> Option<String> {
+            self.0 += 1;
+            (self.0 < 3).then(|| "x".to_string())
+        }
+    }
+
+    impl ExactSizeIterator for TooShort {
+        fn len(&self) -> usize {
+            5
+        }
+    }
+
+    #[test]
+    fn alloc_iter_collects_into_the_arena() {
+        let arena = Arena::new(1024);
+        let items = arena.alloc_iter(0..10u32).unwrap();
+        assert_eq!(items, &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert!(arena
+            .alloc_iter(Vec::<u8>::new().into_iter())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn alloc_iter_rejects_a_lying_len() {
+        let arena = Arena::new(1024);
+        assert!(arena.alloc_iter(TooLong(0)).is_none());
+        assert!(arena.alloc_iter(TooShort(0)).is_none());
+        assert_eq!(arena.bytes_used(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "'frame' leaked 8")]
+    fn named_arena_shows_up_in_leak_reports() {
+        let mut arena = Arena::new(100);
+        arena.set_name("frame");
+        assert!(format!("{arena:?}").contains("frame"));
+        unsafe { arena.bump.alloc(layout(8, 1)) };
+        arena.assert_no_leaks();
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[test]
+    fn alloc_returns_a_usable_reference() {
+        let arena = Arena::new(64);
+        let point = arena.alloc(Point { x: 1, y: 2 });
+        point.x += 10;
+        let count = arena.alloc(7u64);
+        *count += 1;
+        assert_eq!(*point, Point { x: 11, y: 2 });
+        assert_eq!(*count, 8);
+        assert!(arena.try_alloc_value([0u8; 100]).is_none());
+
+        // `GlobalAlloc::alloc` is still there, by way of `bump`.
+        let block = unsafe { arena.bump.alloc(layout(8, 8)) };
+        assert!(!block.is_null());
+    }
+
+    #[test]
+    #[should_panic(expected = "allocate a Layout with try_alloc")]
+    fn alloc_refuses_a_layout() {
+        let arena = Arena::new(64);
+        arena.alloc(layout(8, 8));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of memory")]
+    fn alloc_panics_when_full() {
+        let arena = Arena::new(8);
+        arena.alloc([0u8; 100]);
+    }
+
+    #[cfg(feature = "single-threaded")]
+    #[test]
+    fn cell_cursor_allocates_and_resets() {
+        let arena = Arena::new(256);
+        let first = arena.try_alloc(layout(16, 8)).unwrap();
+        let second = arena.try_alloc(layout(16, 8)).unwrap();
+        assert_eq!(second.as_ptr() as usize - first.as_ptr() as usize, 16);
+        assert_eq!(arena.bytes_used(), 32);
+        assert_eq!(arena.alloc_count(), 2);
+
+        unsafe { arena.reset() };
+        assert_eq!(arena.bytes_used(), 0);
+        assert_eq!(arena.try_alloc(layout(16, 8)).unwrap(), first);
+    }
+
+    #[test]
+    fn drain_returns_the_bytes_and_empties_the_arena() {
+        let mut arena = Arena::new(64);
+        arena.alloc(*b"hey");
+        arena.alloc(*b"yo");
+        assert_eq!(arena.drain(), b"heyyo");
+        assert!(arena.is_empty());
+        assert!(arena.drain().is_empty());
+    }
+
+    #[test]
+    fn next_block_realigns_after_an_odd_sized_one() {
+        let arena = Arena::new(64).align_base_to(8);
+        unsafe {
+            let first = arena.bump.alloc(layout(3, 4)) as usize;
+            let second = arena.bump.alloc(layout(4, 4)) as usize;
+            assert_eq!(second - first, 4);
+            // Only the one byte rounding 3 up to 4 is lost.
+            assert_eq!(arena.bytes_wasted(), 1);
+            let third = arena.bump.alloc(layout(1, 1)) as usize;
+            assert_eq!(third, second + 4);
+        }
+    }
+
+    #[test]
+    fn pressure_tracks_how_full_the_heap_is() {
+        let arena = Arena::new(1000);
+        unsafe { arena.bump.alloc(layout(900, 1)) };
+        assert!((arena.pressure() - 0.9).abs() < 1e-6);
+        assert!(!arena.is_under_pressure());
+        arena.set_pressure_threshold(0.8);
+        assert!(arena.is_under_pressure());
+        assert_eq!(Arena::new(0).pressure(), 1.0);
+    }
+
+    #[test]
+    fn free_above_poisons_the_reclaimed_bytes() {
+        let arena = Arena::new(1024).align_base_to(8);
+        arena.set_poison(true);
+        unsafe {
+            arena.bump.alloc(layout(16, 8));
+            let mark = arena.mark();
+            let block = arena.bump.alloc(layout(32, 8));
+            core::ptr::write_bytes(block, 1, 32);
+            arena.bump.alloc(layout(8, 8));
+
+            arena.free_above(mark);
+            assert_eq!(arena.bytes_used(), 16);
+            let reclaimed = core::slice::from_raw_parts(block, 40);
+            assert!(reclaimed.iter().all(|&b| b == POISON_BYTE));
+        }
+    }
+
+    #[test]
+    fn free_above_without_poison_leaves_the_bytes() {
+        let arena = Arena::new(1024);
+        unsafe {
+            let mark = arena.mark();
+            let block = arena.bump.alloc(layout(16, 8));
+            core::ptr::write_bytes(block, 1, 16);
+            arena.free_above(mark);
+            assert!(arena.is_empty());
+            assert!(core::slice::from_raw_parts(block, 16)
+                .iter()
+                .all(|&b| b == 1));
+        }
+    }
+
+    #[test]
+    fn largest_free_block_sees_through_fragmentation() {
+        let arena = Arena::new(256).align_base_to(8);
+        arena.enable_free_list();
+        unsafe {
+            let blocks: Vec<_> = (0..7).map(|_| arena.bump.alloc(layout(32, 8))).collect();
+            for i in [0, 2, 4] {
+                arena.bump.dealloc(blocks[i], layout(32, 8));
+            }
+        }
+        // Three freed blocks plus the 32-byte tail.
+        assert_eq!(arena.bytes_remaining(), 4 * 32);
+        assert_eq!(arena.largest_free_block(), 32);
+        assert!(arena.largest_free_block() < arena.bytes_remaining());
+    }
+
+    #[cfg(all(feature = "profile-ring", not(feature = "single-threaded")))]
+    #[test]
+    fn events_drain_from_another_thread() {
+        let arena = Arena::new(1 << 16);
+        let expected: Vec<_> = (1..=5)
+            .map(|i| {
+                let block = unsafe { arena.bump.alloc(layout(i * 8, 8)) };
+                (block as usize - arena.bump.heap_start(), i * 8)
+            })
+            .collect();
+
+        let events = std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut events = Vec::new();
+                arena.drain_events(&mut events);
+                events
+            })
+            .join()
+            .unwrap()
+        });
+        let seen: Vec<_> = events.iter().map(|e| (e.offset, e.size)).collect();
+        assert_eq!(seen, expected);
+        assert!(events.iter().all(|e| e.timestamp > 0));
+
+        let mut events = Vec::new();
+        arena.drain_events(&mut events);
+        assert!(events.is_empty());
+    }
+
+    #[cfg(feature = "profile-ring")]
+    #[test]
+    fn full_event_ring_drops_newer_events() {
+        let arena = Arena::new(1 << 16);
+        for _ in 0..PROFILE_RING_SLOTS + 10 {
+            unsafe { arena.bump.alloc(layout(8, 8)) };
+        }
+        let mut events = Vec::new();
+        arena.drain_events(&mut events);
+        assert_eq!(events.len(), PROFILE_RING_SLOTS);
+
+        unsafe { arena.bump.alloc(layout(8, 8)) };
+        events.clear();
+        arena.drain_events(&mut events);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn moving_realloc_reuses_a_free_block() {
+        let arena = Arena::new(1024).align_base_to(8);
+        arena.enable_free_list();
+        unsafe {
+            let hole = arena.bump.alloc(layout(64, 8));
+            arena.bump.alloc(layout(8, 8));
+            let block = arena.bump.alloc(layout(16, 8));
+            *block = 7;
+            arena.bump.alloc(layout(8, 8));
+            arena.bump.dealloc(hole, layout(64, 8));
+
+            let cursor = arena.bump.next.load(Ordering::Relaxed);
+            let moved = arena.bump.realloc(block, layout(16, 8), 64);
+            assert_eq!(moved, hole);
+            assert_eq!(*moved, 7);
+            assert_eq!(arena.bump.next.load(Ordering::Relaxed), cursor);
+        }
+    }
+
+    #[test]
+    fn realloc_resizes_the_top_block_in_place() {
+        let arena = Arena::new(4096).align_base_to(8);
+        unsafe {
+            arena.bump.alloc(layout(16, 8));
+            let top = arena.bump.alloc(layout(1024, 8));
+            *top = 3;
+            let shrunk = arena.bump.realloc(top, layout(1024, 8), 16);
+            assert_eq!(shrunk, top);
+            assert_eq!(*shrunk, 3);
+            assert_eq!(arena.bytes_used(), 32);
+            assert_eq!(arena.bytes_wasted(), 0);
+
+            let grown = arena.bump.realloc(shrunk, layout(16, 8), 40);
+            assert_eq!(grown, top);
+            assert_eq!(arena.bytes_used(), 56);
+        }
+    }
+
+    #[test]
+    fn shrinking_a_buried_block_moves_it() {
+        let arena = Arena::new(4096).align_base_to(8);
+        unsafe {
+            let buried = arena.bump.alloc(layout(64, 8));
+            arena.bump.alloc(layout(8, 8));
+            let moved = arena.bump.realloc(buried, layout(64, 8), 16);
+            assert_ne!(moved, buried);
+            assert_eq!(arena.bytes_wasted(), 64);
+        }
+    }
+
+    #[test]
+    fn checked_layout_names_the_problem() {
+        assert_eq!(
+            checked_layout(8, 3),
+            Err(LayoutBuildError::AlignNotPowerOfTwo)
+        );
+        assert_eq!(
+            checked_layout(8, 0),
+            Err(LayoutBuildError::AlignNotPowerOfTwo)
+        );
+        assert_eq!(
+            checked_layout(usize::MAX, 8),
+            Err(LayoutBuildError::SizeOverflow)
+        );
+        assert_eq!(checked_layout(8, 4), Ok(layout(8, 4)));
+    }
+
+    #[test]
+    fn free_generation_rewinds_the_newest_frame() {
+        let mut arena = Arena::new(1024).align_base_to(8);
+        unsafe {
+            let first = arena.begin_generation();
+            arena.bump.alloc(layout(16, 8));
+            let second = arena.begin_generation();
+            arena.bump.alloc(layout(32, 8));
+            let third = arena.begin_generation();
+            let third_start = arena.mark().position;
+            arena.bump.alloc(layout(64, 8));
+
+            assert!(arena.free_generation(third));
+            assert_eq!(arena.mark().position, third_start);
+            // Not the newest, so deferred.
+            assert!(!arena.free_generation(first));
+            assert_eq!(arena.bytes_used(), 48);
+            // Takes the deferred one with it.
+            assert!(arena.free_generation(second));
+            assert!(arena.is_empty());
+            assert!(!arena.free_generation(second));
+        }
+    }
+
+    #[test]
+    fn heap_range_contains_the_blocks() {
+        let arena = Arena::new(256);
+        let block = unsafe { arena.bump.alloc(Layout::new::<u64>()) } as usize;
+        let (start, end) = arena.heap_range();
+        assert!(start <= block && block + 8 <= end);
+        assert_eq!(end - start, 256);
+        assert_eq!(BumpAllocator::new_uninitialized().heap_range(), (0, 0));
+    }
+
+    #[test]
+    fn alloc_fast_never_takes_the_slow_path() {
+        let arena = Arena::new(128);
+        arena.enable_free_list();
+        unsafe {
+            let freed = arena.bump.alloc(layout(32, 8));
+            arena.bump.alloc(layout(8, 8));
+            arena.bump.dealloc(freed, layout(32, 8));
+            while !arena.alloc_fast(layout(8, 8)).is_null() {}
+            // The free list could serve it, but only `alloc` looks there.
+            assert!(arena.alloc_fast(layout(32, 8)).is_null());
+            assert_eq!(arena.bump.alloc(layout(32, 8)), freed);
+        }
+        assert!(arena.alloc_fast(layout(8, 256)).is_null());
+        assert!(BumpAllocator::new_uninitialized()
+            .alloc_fast(layout(8, 8))
+            .is_null());
+    }
+
+    #[test]
+    fn alloc_fast_never_grows_a_chunked_arena() {
+        let arena = ChunkedArena::with_policy(128, GrowthPolicy::Fixed(128));
+        while !arena.alloc_fast(layout(8, 8)).is_null() {}
+        assert_eq!(arena.chunk_count(), 1);
+        assert_eq!(arena.stats().grow_events, 0);
+        // `alloc` grows as usual, and the fast path follows it to the new
+        // chunk.
+        assert!(!arena.alloc(layout(8, 8)).is_null());
+        assert_eq!(arena.chunk_count(), 2);
+        assert!(!arena.alloc_fast(layout(8, 8)).is_null());
+        assert_eq!(arena.chunk_count(), 2);
+    }
+
+    #[test]
+    fn alloc_fast_blocks_are_not_tracked() {
+        let mut arena = Arena::new(128);
+        arena.enable_tracking(8);
+        let fast = arena.alloc_fast(layout(16, 8));
+        let slow = arena.try_alloc(layout(8, 8)).unwrap();
+        assert_eq!(
+            arena.allocated_offsets(),
+            [slow.as_ptr() as usize - arena.heap_start()]
+        );
+        unsafe { arena.bump.dealloc(fast, layout(16, 8)) };
+        assert_eq!(arena.allocations().len(), 1);
+        arena.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn alignment_histogram_counts_each_alignment() {
+        let arena = Arena::new(1024);
+        for align in [1, 8, 64, 8] {
+            unsafe { arena.bump.alloc(layout(4, align)) };
+        }
+        let histogram = arena.alignment_histogram();
+        assert_eq!((histogram[0], histogram[3], histogram[6]), (1, 2, 1));
+        assert_eq!(histogram.iter().sum::<usize>(), 4);
+        arena.reset_stats();
+        assert_eq!(arena.alignment_histogram(), [0; HISTOGRAM_BUCKETS]);
+    }
+
+    #[test]
+    fn realloc_align_moves_to_a_stronger_alignment() {
+        let arena = Arena::new(1024).align_base_to(64);
+        unsafe {
+            arena.bump.alloc(layout(8, 8));
+            let block = arena.bump.alloc(layout(16, 8));
+            assert_ne!(block as usize % 64, 0);
+            for i in 0..16 {
+                *block.add(i) = i as u8;
+            }
+
+            let moved = arena.realloc_align(block, layout(16, 8), 32, 64);
+            assert_eq!(moved as usize % 64, 0);
+            assert!((0..16).all(|i| *moved.add(i) == i as u8));
+            // Already aligned and on top, so it grows in place.
+            let grown = arena.realloc_align(moved, layout(32, 64), 48, 64);
+            assert_eq!(grown, moved);
+            assert!(arena
+                .realloc_align(grown, layout(48, 64), usize::MAX, 64)
+                .is_null());
+        }
+    }
+
+    const _: () = assert!(FixedBump::<64>::capacity() == 64);
+
+    #[test]
+    fn fixed_bump_allocates_within_its_buffer() {
+        let fixed = FixedBump::<64>::new();
+        let block = fixed.alloc(layout(8, 8));
+        assert!(!block.is_null() && (block as usize).is_multiple_of(8));
+        assert!(fixed.alloc(layout(64, 1)).is_null());
+        assert!(!fixed.alloc(layout(8, 1)).is_null());
+        assert!(fixed.bytes_used() <= FixedBump::<64>::capacity());
+    }
+
+    #[test]
+    fn dealloc_of_null_does_nothing() {
+        let arena = Arena::new(64);
+        unsafe {
+            arena.bump.alloc(Layout::new::<u64>());
+            let before = arena.mark().position;
+            arena.bump.dealloc(null_mut(), Layout::new::<u64>());
+            arena.bump.dealloc(null_mut(), layout(0, 1));
+            assert_eq!(arena.mark().position, before);
+        }
+        assert_eq!(arena.bytes_wasted(), 0);
+    }
+
+    #[test]
+    fn alloc_with_builds_the_value_in_place() {
+        let arena = Arena::new(1 << 20);
+        let big = arena.alloc_with(|| [7u64; 16384]).unwrap();
+        assert!(big.iter().all(|&x| x == 7));
+
+        let small = Arena::new(8);
+        let mut called = false;
+        assert!(small
+            .alloc_with(|| {
+                called = true;
+                [0u8; 64]
+            })
+            .is_none());
+        assert!(!called);
+    }
+
+    // Whether this is a child process started by `run_in_child`.
+    #[cfg(target_os = "linux")]
+    fn in_child() -> bool {
+        std::env::var_os("BUMP_TEST_CHILD").is_some()
+    }
+
+    // Run just the test `name` again in a child process, with `envs` set and
+    // `in_child` true, and collect its stderr. Faults and exit-time output
+    // can't be observed in-process, so tests that need them do that part
+    // only in the child.
+    #[cfg(target_os = "linux")]
+    fn run_in_child(name: &str, envs: &[(&str, &str)]) -> std::process::Output {
+        std::process::Command::new(std::env::current_exe().unwrap())
+            .args([name, "--exact", "--test-threads=1"])
+            .env("BUMP_TEST_CHILD", "1")
+            .envs(envs.iter().copied())
+            .stdout(std::process::Stdio::null())
+            .output()
+            .unwrap()
+    }
+
+    // The signal that killed `run_in_child(name, &[])`, if any.
+    #[cfg(target_os = "linux")]
+    fn signal_in_child(name: &str) -> Option<i32> {
+        use std::os::unix::process::ExitStatusExt;
+
+        run_in_child(name, &[]).status.signal()
+    }
+
+    #[cfg(all(target_os = "linux", feature = "strict-reset"))]
+    #[test]
+    fn strict_reset_faults_on_a_stale_pointer() {
+        let arena = MmapArena::new(10_000).unwrap();
+        arena.set_strict_reset(true);
+        let stale = arena.alloc(layout(8, 8));
+        unsafe {
+            *stale = 5;
+            arena.reset();
+        }
+        if in_child() {
+            unsafe { core::ptr::read_volatile(stale) };
+            return;
+        }
+        let name = "tests::strict_reset_faults_on_a_stale_pointer";
+        assert_eq!(signal_in_child(name), Some(libc::SIGSEGV));
+
+        // The next allocation makes the heap accessible again.
+        let fresh = arena.alloc(layout(8, 8));
+        assert_eq!(fresh, stale);
+        unsafe {
+            *fresh = 9;
+            assert_eq!(*fresh, 9);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn plain_reset_leaves_the_heap_readable() {
+        let arena = MmapArena::new(100).unwrap();
+        assert_eq!(arena.capacity() % 4096, 0);
+        let stale = arena.alloc(layout(8, 8));
+        unsafe {
+            *stale = 5;
+            arena.reset();
+            assert_eq!(core::ptr::read_volatile(stale), 5);
+        }
+    }
+
+    #[test]
+    fn arena_owns_its_buffer() {
+        let arena = Arena::new(64 * 1024);
+        assert_eq!(arena.capacity(), 64 * 1024);
+        let block = arena.try_alloc(layout(1024, 16)).unwrap();
+        unsafe { core::ptr::write_bytes(block.as_ptr(), 0xEE, 1024) };
+        assert_eq!(arena.bytes_used(), 1024);
+        // Dropping it frees the buffer; Miri checks nothing leaks.
+        drop(arena);
+    }
+
+    #[cfg(not(feature = "single-threaded"))]
+    #[test]
+    fn overlap_check_holds_under_concurrent_allocation() {
+        let mut arena = Arena::new(1 << 20);
+        arena.enable_tracking(100_000);
+        arena.enable_free_list();
+        let arena = &arena;
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                s.spawn(move || unsafe {
+                    let mut mine = Vec::new();
+                    for i in 0..2000 {
+                        let block_layout = layout(1 + (i * 7 + t) % 40, 1 << (i % 4));
+                        let block = arena.bump.alloc(block_layout);
+                        assert!(!block.is_null());
+                        mine.push((block, block_layout));
+                        if i % 3 == 0 {
+                            let (block, block_layout) = mine.swap_remove(i % mine.len());
+                            arena.bump.dealloc(block, block_layout);
+                        }
+                    }
+                });
+            }
+        });
+        let live = arena.allocations();
+        assert_eq!(live.len(), 8 * (2000 - 667));
+        for pair in live.windows(2) {
+            assert!(pair[0].offset + pair[0].size <= pair[1].offset);
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "overlapping live allocation")]
+    fn overlap_check_catches_a_cursor_bug() {
+        let mut arena = Arena::new(1024);
+        arena.enable_tracking(10);
+        // Through `try_alloc`, since the panic would abort the process if it
+        // came out of `GlobalAlloc::alloc`.
+        let block = arena.try_alloc(layout(16, 8)).unwrap();
+        arena
+            .bump
+            .next
+            .store(block.as_ptr() as usize + 8, Ordering::Relaxed);
+        let _ = arena.try_alloc(layout(16, 8));
+    }
+
+    #[test]
+    fn tracking_follows_resizes_rewinds_and_frees() {
+        let mut arena = Arena::new(1024).align_base_to(8);
+        arena.enable_tracking(10);
+        unsafe {
+            let block = arena.bump.alloc(layout(16, 8));
+            let grown = arena.bump.realloc(block, layout(16, 8), 32);
+            assert_eq!(grown, block);
+            assert_eq!(
+                arena.allocations(),
+                vec![AllocInfo {
+                    offset: 0,
+                    size: 32
+                }]
+            );
+            let mark = arena.mark();
+            arena.bump.alloc(layout(8, 8));
+            arena.rewind(mark);
+            assert_eq!(arena.allocations().len(), 1);
+            arena.bump.dealloc(grown, layout(32, 8));
+            assert!(arena.allocations().is_empty());
+        }
+    }
+
+    #[test]
+    fn capacity_for_accounts_for_alignment() {
+        let arena = Arena::new(1000).align_base_to(8);
+        unsafe { arena.bump.alloc(layout(13, 1)) };
+        assert_eq!(arena.capacity_for::<u64>(), (1000 - 16) / 8);
+        assert_eq!(arena.capacity_for::<u8>(), 987);
+        assert_eq!(arena.capacity_for::<()>(), usize::MAX);
+
+        let tiny = Arena::new(4);
+        unsafe { tiny.bump.alloc(layout(1, 1)) };
+        assert_eq!(tiny.capacity_for::<u64>(), 0);
+    }
+
+    #[test]
+    fn arena_vec_grows_in_place_while_on_top() {
+        let arena = Arena::new(1 << 16);
+        let mut vec = ArenaVec::new_in(&arena);
+        vec.push(0u32);
+        let first = vec.as_ptr();
+        for i in 1..1000u32 {
+            vec.push(i);
+            assert_eq!(vec.as_ptr(), first);
+        }
+        assert!(vec.iter().copied().eq(0..1000));
+
+        // Once something else is on top, growing means moving.
+        unsafe { arena.bump.alloc(Layout::new::<u8>()) };
+        let cap = vec.capacity() as u32;
+        for i in 1000..=cap {
+            vec.push(i);
+        }
+        assert_ne!(vec.as_ptr(), first);
+        assert!(vec.iter().copied().eq(0..=cap));
+    }
+
+    #[test]
+    fn arena_vec_drops_its_elements() {
+        let arena = Arena::new(4096);
+        let shared = std::rc::Rc::new(());
+        {
+            let mut vec = ArenaVec::new_in(&arena);
+            for _ in 0..10 {
+                vec.push(shared.clone());
+            }
+            assert_eq!(std::rc::Rc::strong_count(&shared), 11);
+        }
+        assert_eq!(std::rc::Rc::strong_count(&shared), 1);
+
+        let mut units = ArenaVec::new_in(&arena);
+        for _ in 0..100 {
+            units.push(());
+        }
+        assert_eq!(units.len(), 100);
+    }
+
+    #[test]
+    fn fixed_growth_adds_equal_chunks() {
+        let mut arena = ChunkedArena::new(8192);
+        arena.set_growth_policy(GrowthPolicy::Fixed(8192));
+        for _ in 0..40 {
+            assert!(!arena.alloc(layout(1024, 8)).is_null());
+        }
+        assert_eq!(arena.chunk_count(), 5);
+        assert_eq!(arena.bytes_used(), 40 * 1024);
+
+        arena.reset();
+        for _ in 0..40 {
+            arena.alloc(layout(1024, 8));
+        }
+        assert_eq!(arena.chunk_count(), 5);
+    }
+
+    #[test]
+    fn default_growth_doubles_the_arena() {
+        let arena = ChunkedArena::new(4096);
+        let mut totals = vec![arena.capacity()];
+        while totals.len() < 4 {
+            arena.alloc(layout(1024, 8));
+            if arena.capacity() != *totals.last().unwrap() {
+                totals.push(arena.capacity());
+            }
+        }
+        assert_eq!(totals, [4096, 8192, 16384, 32768]);
+    }
+
+    #[test]
+    fn custom_growth_still_fits_the_allocation() {
+        let arena = ChunkedArena::with_policy(100, GrowthPolicy::Custom(|_| 100));
+        assert!(!arena.alloc(layout(5000, 8)).is_null());
+        assert_eq!(arena.chunk_count(), 2);
+    }
+
+    #[test]
+    fn panicking_oom_handler_poisons_try_alloc() {
+        fn boom(_: &OomReport) {
+            panic!("oom handler");
+        }
+        let arena = Arena::new(64);
+        arena.set_oom_handler(boom);
+        let block = arena.try_alloc(Layout::new::<u64>()).unwrap();
+        assert!(!arena.is_poisoned());
+
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| arena.try_alloc(layout(128, 8))));
+        assert!(result.is_err());
+        assert!(arena.is_poisoned());
+        assert_eq!(arena.try_alloc(layout(1, 1)), Err(AllocError::Poisoned));
+        assert!(arena.alloc_fast(layout(1, 1)).is_null());
+        unsafe {
+            assert!(arena.bump.alloc(layout(8, 8)).is_null());
+            // Frees still work.
+            arena.bump.dealloc(block.as_ptr(), Layout::new::<u64>());
+        }
+        assert_eq!(arena.bytes_used(), 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn panicking_oom_handler_aborts_global_alloc() {
+        fn boom(_: &OomReport) {
+            panic!("oom handler");
+        }
+        if in_child() {
+            let arena = Arena::new(64);
+            arena.set_oom_handler(boom);
+            let _ = std::panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+                arena.bump.alloc(layout(128, 8))
+            }));
+            return;
+        }
+        let name = "tests::panicking_oom_handler_aborts_global_alloc";
+        assert_eq!(signal_in_child(name), Some(libc::SIGABRT));
+    }
+
+    #[test]
+    fn shrink_chunks_to_caps_retained_memory() {
+        let mut arena = ChunkedArena::with_policy(4096, GrowthPolicy::Fixed(4096));
+        for _ in 0..20 {
+            arena.alloc(layout(1024, 8));
+        }
+        assert_eq!(arena.chunk_count(), 5);
+        // Every chunk is in use.
+        assert_eq!(arena.shrink_chunks_to(0), 20480);
+
+        arena.reset();
+        assert_eq!(arena.shrink_chunks_to(10_000), 8192);
+        assert!(arena.capacity() <= 10_000);
+        assert_eq!(arena.shrink_chunks_to(0), 4096);
+        assert_eq!(arena.chunk_count(), 1);
+    }
+
+    #[cfg(not(feature = "single-threaded"))]
+    #[test]
+    fn aggregate_stats_sums_every_registered_arena() {
+        static REGISTRY: StatsRegistry = StatsRegistry::new();
+        std::thread::scope(|s| {
+            for count in [3, 5] {
+                s.spawn(move || {
+                    let arena: &'static Arena = Box::leak(Box::new(Arena::new(4096)));
+                    assert!(REGISTRY.register(arena));
+                    assert!(!REGISTRY.register(arena));
+                    for _ in 0..count {
+                        unsafe { arena.bump.alloc(Layout::new::<u64>()) };
+                    }
+                });
+            }
+        });
+        let stats = REGISTRY.aggregate_stats();
+        assert_eq!(stats.alloc_count, 8);
+        assert_eq!(stats.bytes_used, 64);
+        assert_eq!(stats.peak, 64);
+    }
+
+    #[test]
+    fn bulk_alloc_is_all_or_nothing() {
+        let arena = Arena::new(256);
+        let layouts = [layout(3, 1), layout(16, 8), layout(5, 64), layout(4, 4)];
+        let blocks = arena.bulk_alloc(&layouts).unwrap();
+        assert_eq!(blocks.len(), 4);
+        for (block, block_layout) in blocks.iter().zip(&layouts) {
+            assert!((*block as usize).is_multiple_of(block_layout.align()));
+        }
+        for pair in blocks.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+
+        let before = arena.mark().position;
+        assert!(arena
+            .bulk_alloc(&[layout(100, 8), layout(200, 8)])
+            .is_none());
+        assert_eq!(arena.mark().position, before);
+        assert_eq!(arena.bulk_alloc(&[]).map(|blocks| blocks.len()), Some(0));
+    }
+
+    #[test]
+    fn snapshot_loads_into_a_fresh_arena() {
+        let arena = Arena::new(512);
+        let handle = arena.alloc_handle(Layout::new::<u64>()).unwrap();
+        unsafe {
+            (arena.resolve(&handle).unwrap() as *mut u64).write_unaligned(0xABCD);
+            arena.bump.alloc(layout(5, 1));
+        }
+
+        let snapshot = arena.snapshot();
+        let copy = Arena::from_snapshot(&snapshot).unwrap();
+        assert_eq!(copy.snapshot(), snapshot);
+        assert_eq!(copy.bytes_used(), arena.bytes_used());
+        let handle = Handle {
+            generation: copy.generation(),
+            ..handle
+        };
+        let value = unsafe { (copy.resolve(&handle).unwrap() as *const u64).read_unaligned() };
+        assert_eq!(value, 0xABCD);
+
+        let bad = ArenaSnapshot {
+            capacity: 4,
+            cursor: 8,
+            bytes: vec![0; 8],
+        };
+        assert!(Arena::from_snapshot(&bad).is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_round_trips_through_serde() {
+        let arena = Arena::new(64);
+        arena.alloc(*b"some bytes");
+        let json = serde_json::to_string(&arena.snapshot()).unwrap();
+        let snapshot: ArenaSnapshot = serde_json::from_str(&json).unwrap();
+        let mut copy = Arena::from_snapshot(&snapshot).unwrap();
+        assert_eq!(copy.capacity(), 64);
+        assert_eq!(copy.drain(), b"some bytes");
+    }
+
+    #[test]
+    fn write_summary_lists_the_counters() {
+        let mut arena = Arena::new(512);
+        arena.set_name("scratch");
+        unsafe {
+            arena.bump.alloc(layout(5, 1));
+            arena.bump.alloc(layout(100, 1));
+        }
+        let mut summary = String::new();
+        arena.write_summary(&mut summary).unwrap();
+        assert_eq!(
+            summary,
+            "scratch: 2 allocations, peak 105 bytes\n  >= 4 bytes: 1\n  >= 64 bytes: 1\n"
+        );
+    }
+
+    #[cfg(all(target_os = "linux", not(feature = "single-threaded")))]
+    #[test]
+    fn bump_trace_dumps_the_summary_at_exit() {
+        if in_child() {
+            trace_at_exit_if_requested();
+            unsafe { GLOBAL.alloc(layout(24, 1)) };
+            return;
+        }
+        let name = "tests::bump_trace_dumps_the_summary_at_exit";
+        let traced = run_in_child(name, &[("BUMP_TRACE", "1")]);
+        assert!(traced.status.success());
+        let stderr = String::from_utf8_lossy(&traced.stderr);
+        assert!(
+            stderr.contains("bump allocator: 1 allocations, peak 24 bytes\n"),
+            "{stderr}"
+        );
+        assert!(stderr.contains("  >= 16 bytes: 1\n"), "{stderr}");
+
+        let quiet = run_in_child(name, &[]);
+        assert!(!String::from_utf8_lossy(&quiet.stderr).contains("bump allocator"));
+    }
+
+    #[test]
+    fn align_helpers_work_on_every_width() {
+        assert_eq!(checked_align_up(u64::MAX - 3, 8u64), None);
+        assert_eq!(checked_align_up(u64::MAX - 7, 8u64), Some(u64::MAX - 7));
+        assert_eq!(checked_align_up(13u32, 8), Some(16));
+        assert_eq!(checked_align_up(250u8, 8), None);
+        // Not a power of two.
+        assert_eq!(checked_align_up(13u8, 6), None);
+        assert_eq!(align_down(13u16, 8), Some(8));
+        assert_eq!(align_down(13usize, 0), None);
+    }
+
+    #[test]
+    fn reset_to_capacity_shrinks_the_buffer() {
+        let mut arena = Arena::new(1 << 16);
+        for _ in 0..100 {
+            unsafe { *arena.bump.alloc(Layout::new::<u64>()) = 0xFF };
+        }
+        arena.reset_to_capacity(1024);
+        assert_eq!(arena.capacity(), 1024);
+        assert!(arena.is_empty());
+        let (start, end) = arena.heap_range();
+        assert_eq!(end - start, 1024);
+        unsafe {
+            // Dirty bytes kept from before still get cleared.
+            let block = arena.bump.alloc_zeroed(Layout::new::<u64>());
+            assert_eq!(*block, 0);
+            assert!((start..end).contains(&(block as usize)));
+            assert!(arena.bump.alloc(layout(1020, 1)).is_null());
+        }
+
+        // Growing isn't shrinking; it only resets.
+        arena.reset_to_capacity(4096);
+        assert_eq!(arena.capacity(), 1024);
+        assert!(arena.is_empty());
+    }
+
+    #[test]
+    fn content_eq_compares_the_used_bytes() {
+        let small = Arena::new(256);
+        let big = Arena::new(512);
+        for arena in [&small, &big] {
+            arena.alloc(7u32);
+            arena.alloc(0xABu8);
+        }
+        assert!(small.content_eq(&big));
+        *big.alloc(1u8) = 1;
+        assert!(!small.content_eq(&big));
+
+        let other = Arena::new(256);
+        other.alloc(7u32);
+        other.alloc(0xACu8);
+        assert!(!small.content_eq(&other));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn guard_page_faults_past_the_heap() {
+        let arena = MmapArena::new(4096).unwrap();
+        let (start, end) = arena.heap_range();
+        for addr in start..end {
+            unsafe { core::ptr::write_volatile(addr as *mut u8, 1) };
+        }
+        if in_child() {
+            unsafe { core::ptr::write_volatile(end as *mut u8, 1) };
+            return;
+        }
+        let name = "tests::guard_page_faults_past_the_heap";
+        assert_eq!(signal_in_child(name), Some(libc::SIGSEGV));
+    }
+
+    #[test]
+    fn packed_blocks_are_contiguous() {
+        let arena = Arena::new(256);
+        let mut end = arena.alloc_packed(3) as usize + 3;
+        for size in [5, 1, 7, 2] {
+            let block = arena.alloc_packed(size) as usize;
+            assert_eq!(block, end);
+            end = block + size;
+        }
+        assert_eq!(arena.bytes_used(), 18);
+        assert_eq!(arena.bytes_wasted(), 0);
+        assert!(arena.alloc_packed(1000).is_null());
+    }
+
+    #[test]
+    fn rewind_full_restores_the_statistics() {
+        let arena = Arena::new(1024);
+        arena.alloc(1u64);
+        let checkpoint = arena.checkpoint_full();
+        let before = (
+            arena.stats(),
+            arena.size_histogram(),
+            arena.alignment_histogram(),
+        );
+        for i in 0..10 {
+            arena.alloc([i as u8; 50]);
+        }
+        assert_ne!(arena.stats(), before.0);
+        unsafe { arena.rewind_full(&checkpoint) };
+        let after = (
+            arena.stats(),
+            arena.size_histogram(),
+            arena.alignment_histogram(),
+        );
+        assert_eq!(after, before);
+    }
+
+    #[test]
+    fn oversized_requests_fail_without_side_effects() {
+        let arena = Arena::new(128);
+        arena.alloc(1u8);
+        let stats = arena.stats();
+        let cursor = arena.mark().position;
+        assert!(unsafe { arena.bump.alloc(layout(129, 1)) }.is_null());
+        assert_eq!(
+            arena.try_alloc(layout(usize::MAX / 4, 8)),
+            Err(AllocError::OutOfMemory)
+        );
+        assert_eq!(arena.mark().position, cursor);
+        assert_eq!(arena.stats(), stats);
+        assert_eq!(arena.oom_count(), 2);
+        assert_eq!(arena.bytes_wasted(), 0);
+    }
+
+    #[test]
+    fn reclaim_rolls_back_the_top_block() {
+        let arena = Arena::new(4096);
+        arena.alloc(1u32);
+        let cursor = arena.mark().position;
+        {
+            let mut buf = Reclaim::new_in([0u32; 256], &arena).unwrap();
+            buf[3] = 4;
+            assert_eq!(buf[3], 4);
+        }
+        assert_eq!(arena.mark().position, cursor);
+
+        // Buried under a later block, it can only be written off.
+        let buried = Reclaim::new_in(5u64, &arena).unwrap();
+        arena.alloc(1u8);
+        let wasted = arena.bytes_wasted();
+        drop(buried);
+        assert_eq!(arena.bytes_wasted(), wasted + 8);
+        assert!(Reclaim::new_in([0u8; 5000], &arena).is_none());
+    }
+
+    #[test]
+    fn allocated_offsets_lists_the_live_blocks() {
+        let mut arena = Arena::new(1024).align_base_to(8);
+        arena.enable_tracking(8);
+        unsafe {
+            arena.bump.alloc(layout(8, 8));
+            let middle = arena.bump.alloc(layout(16, 8));
+            arena.bump.alloc(layout(8, 8));
+            arena.bump.dealloc(middle, layout(16, 8));
+        }
+        assert_eq!(arena.allocated_offsets(), vec![0, 24]);
+    }
+
+    #[test]
+    fn affinity_scope_keeps_its_blocks_together() {
+        let arena = Arena::new(1 << 16);
+        let scope = arena.with_affinity("tree");
+        let mut blocks = Vec::new();
+        for i in 0..10 {
+            blocks.push(scope.alloc(layout(24, 8)) as usize);
+            if i % 3 == 0 {
+                unsafe { arena.bump.alloc(layout(8, 8)) };
+            }
+        }
+        for pair in blocks.windows(2) {
+            assert_eq!(pair[1] - pair[0], 24);
+        }
+        drop(scope);
+
+        // Growing past the first guess, and handing back the slack on drop.
+        let cursor = arena.mark().position;
+        let scope = arena.with_affinity("big");
+        let big = scope.alloc(layout(6000, 8));
+        assert!(!big.is_null());
+        assert_eq!(scope.alloc(layout(8, 8)) as usize, big as usize + 6000);
+        drop(scope);
+        assert_eq!(arena.mark().position, cursor + 6008);
+
+        let tiny = Arena::new(64);
+        let scope = tiny.with_affinity("tiny");
+        assert!(!scope.alloc(layout(16, 8)).is_null());
+        assert!(scope.alloc(layout(100, 8)).is_null());
+    }
+
+    #[test]
+    fn usable_capacity_is_what_a_reset_heap_offers() {
+        let arena = Arena::new(100);
+        unsafe {
+            arena.bump.alloc(Layout::new::<u8>());
+            arena.reset();
+        }
+        assert_eq!(arena.usable_capacity(), arena.bytes_remaining());
+        // The header at the start of the mapping isn't part of the heap, and
+        // nor is the guard page at the end.
+        #[cfg(target_os = "linux")]
+        {
+            let mapped = MmapArena::new(5000).unwrap();
+            assert_eq!(
+                mapped.usable_capacity(),
+                mapped.capacity() - mapped.header_len
+            );
+            assert!(mapped.header_len >= core::mem::size_of::<MmapHeader>());
+            assert!(mapped.usable_capacity() >= 5000);
+            let (start, end) = mapped.heap_range();
+            assert_eq!(end - start, mapped.usable_capacity());
+            assert_eq!(start, mapped.map as usize + mapped.header_len);
+        }
+    }
+
+    #[test]
+    fn try_clone_region_copies_a_sub_range() {
+        let arena = Arena::new(256);
+        arena.alloc(0u64);
+        let original = arena.alloc(*b"hello");
+        let offset = original.as_ptr() as usize - arena.heap_range().0;
+        let copy = arena.try_clone_region(offset, 5).unwrap();
+        assert_eq!(copy, b"hello");
+        copy[0] = b'j';
+        assert_eq!(original, b"hello");
+        assert!(arena.try_clone_region(offset, 100).is_none());
+        assert!(arena.try_clone_region(usize::MAX, 2).is_none());
+    }
+
+    #[test]
+    fn rewind_full_restores_the_statistics_growing_down() {
+        let heap = Box::leak(vec![0u64; 128].into_boxed_slice());
+        let bump = BumpAllocator::new_uninitialized().with_direction(Direction::Down);
+        unsafe { bump.init(heap.as_mut_ptr() as usize, 1024) };
+        let checkpoint = bump.checkpoint_full();
+        let block = unsafe { bump.alloc(layout(64, 8)) };
+        assert!(!block.is_null());
+        unsafe { bump.rewind_full(&checkpoint) };
+        // The rewind itself is ignored, but the statistics still go back.
+        assert_eq!(bump.mark().position, block as usize);
+        assert_eq!(bump.alloc_count(), checkpoint.alloc_count);
+        assert_eq!(bump.peak(), checkpoint.peak);
+        assert_eq!(bump.size_histogram(), checkpoint.size_histogram);
+    }
+
+    #[test]
+    fn used_slice_for_follows_a_moved_handle() {
+        let arena = Arena::new(256).align_base_to(8);
+        let mut pad = arena.alloc_handle(layout(32, 8)).unwrap();
+        let handle = arena.alloc_handle(layout(4, 4)).unwrap();
+        unsafe {
+            core::ptr::copy_nonoverlapping(b"abcd".as_ptr(), arena.resolve(&handle).unwrap(), 4)
+        };
+        assert_eq!(arena.used_slice_for(handle), b"abcd");
+        arena.free_handle(&mut pad);
+
+        let mut dest = Arena::new(256);
+        let mut handles = [pad, handle];
+        assert!(arena.compact_to(&mut dest, &mut handles));
+        assert_ne!(handles[1].offset(), handle.offset());
+        assert_eq!(dest.used_slice_for(handles[1]), b"abcd");
+    }
+
+    #[test]
+    #[should_panic(expected = "no longer live")]
+    fn used_slice_for_a_freed_handle_panics() {
+        let arena = Arena::new(64);
+        let mut handle = arena.alloc_handle(layout(1, 1)).unwrap();
+        arena.free_handle(&mut handle);
+        arena.used_slice_for(handle);
+    }
+
+    #[test]
+    fn min_block_size_rounds_small_blocks_up() {
+        let mut arena = Arena::new(256).align_base_to(8);
+        arena.enable_tracking(8);
+        arena.set_min_block_size(16);
+        let first = arena.try_alloc(layout(4, 4)).unwrap().as_ptr();
+        assert_eq!(arena.bytes_used(), 16);
+        assert_eq!(arena.allocations()[0].size, 16);
+        let second = arena.try_alloc(layout(4, 4)).unwrap().as_ptr();
+        assert_eq!(second as usize - first as usize, 16);
+        unsafe { arena.bump.dealloc(second, layout(4, 4)) };
+        assert_eq!(arena.bytes_used(), 16);
+
+        let blocks = arena.bulk_alloc(&[layout(4, 4), layout(4, 4)]).unwrap();
+        assert_eq!(blocks[1] as usize - blocks[0] as usize, 16);
+        arena.check_invariants().unwrap();
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", not(feature = "single-threaded")))]
+    fn alloc_blocking_wakes_when_space_is_freed() {
+        use std::time::Duration;
+        let arena = MmapArena::new(4096).unwrap();
+        let whole = layout(arena.usable_capacity(), 8);
+        let half = layout(arena.usable_capacity() / 2, 8);
+        let too_big = layout(arena.usable_capacity() + 1, 8);
+        assert!(arena
+            .alloc_blocking(too_big, Duration::from_secs(10))
+            .is_null());
+        let block_until = |free: &dyn Fn()| {
+            std::thread::scope(|s| {
+                let waiter =
+                    s.spawn(|| arena.alloc_blocking(half, Duration::from_secs(60)) as usize);
+                std::thread::sleep(Duration::from_millis(50));
+                let freed = std::time::Instant::now();
+                free();
+                assert_ne!(waiter.join().unwrap(), 0);
+                // Woken, rather than trying one last time at the deadline.
+                assert!(freed.elapsed() < Duration::from_secs(30));
+            });
+        };
+
+        let top = arena.alloc(whole);
+        assert!(arena
+            .alloc_blocking(half, Duration::from_millis(10))
+            .is_null());
+        block_until(&|| unsafe { arena.dealloc(top, whole) });
+
+        unsafe { arena.reset() };
+        let mark = arena.mark();
+        arena.alloc(whole);
+        block_until(&|| unsafe { arena.rewind(mark) });
+
+        arena.alloc(half);
+        block_until(&|| unsafe { arena.reset() });
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", not(feature = "single-threaded")))]
+    fn alloc_blocking_wakes_across_processes() {
+        use std::time::{Duration, Instant};
+        let arena = MmapArena::new_shared(4096).unwrap();
+        let whole = layout(arena.usable_capacity(), 8);
+        let half = layout(arena.usable_capacity() / 2, 8);
+        let top = arena.alloc(whole);
+        let child = unsafe { libc::fork() };
+        assert!(child >= 0);
+        if child == 0 {
+            let ptr = arena.alloc_blocking(half, Duration::from_secs(60));
+            unsafe { libc::_exit(if ptr.is_null() { 1 } else { 0 }) };
+        }
+        // Give the child time to find the arena full and go to sleep.
+        std::thread::sleep(Duration::from_millis(50));
+        let freed = Instant::now();
+        unsafe { arena.dealloc(top, whole) };
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 0);
+        assert!(freed.elapsed() < Duration::from_secs(30));
+        // The child's allocation landed in the shared heap.
+        assert_eq!(arena.bytes_used(), half.size());
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn memory_report_matches_the_stats() {
+        let arena = Arena::new(128);
+        arena.alloc(7u32);
+        assert!(arena.try_alloc(layout(1000, 1)).is_err());
+        let report: serde_json::Value = serde_json::from_str(&arena.memory_report()).unwrap();
+        assert_eq!(report["used"], arena.bytes_used());
+        assert_eq!(report["capacity"], 128);
+        assert_eq!(report["alloc_count"], 1);
+        assert_eq!(report["oom_count"], 1);
+        assert_eq!(report["size_histogram"][2], 1);
+    }
+
+    #[test]
+    fn allocating_at_the_top_of_the_address_space_fails_cleanly() {
+        // Only addresses are worked out here; the heap is never touched.
+        let top = |size| {
+            let bump = BumpAllocator::new_uninitialized();
+            unsafe { bump.init(usize::MAX - size, size) };
+            bump
+        };
+        let bump = top(64);
+        let first = bump.try_alloc(layout(63, 1)).unwrap().as_ptr() as usize;
+        assert_eq!(first, usize::MAX - 64);
+
+        // Aligning the cursor up would wrap past `usize::MAX`.
+        assert_eq!(bump.try_alloc(layout(0, 64)), Err(AllocError::OutOfMemory));
+        assert!(!bump.can_fit(layout(0, 64)));
+        assert!(bump.alloc_fast(layout(0, 64)).is_null());
+        assert!(bump.alloc_with_offset_align(layout(1, 1), 0, 64).is_null());
+        assert_eq!(bump.capacity_for::<[u64; 8]>(), 0);
+
+        // So would adding the size.
+        assert_eq!(bump.try_alloc(layout(2, 1)), Err(AllocError::OutOfMemory));
+        assert!(!bump.can_fit(layout(2, 1)));
+        assert!(bump.alloc_in_range(layout(2, 1), 0, usize::MAX).is_null());
+        assert!(bump.try_alloc(layout(1, 1)).is_ok());
+        assert_eq!(bump.bytes_remaining(), 0);
+        bump.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn chunked_arena_acquires_chunks_only_when_full() {
+        use std::rc::Rc;
+
+        struct Counting {
+            acquired: Rc<Cell<usize>>,
+            released: Rc<Cell<usize>>,
+        }
+
+        unsafe impl ChunkSource for Counting {
+            fn acquire(&mut self, min_size: usize) -> Option<NonNull<[u8]>> {
+                self.acquired.set(self.acquired.get() + 1);
+                VecChunkSource.acquire(min_size)
+            }
+
+            unsafe fn release(&mut self, chunk: NonNull<[u8]>) {
+                self.released.set(self.released.get() + 1);
+                VecChunkSource.release(chunk)
+            }
+        }
+
+        let (acquired, released) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+        let source = Counting {
+            acquired: acquired.clone(),
+            released: released.clone(),
+        };
+        let mut arena = ChunkedArena::with_source(64, GrowthPolicy::Fixed(64), source).unwrap();
+        assert_eq!(acquired.get(), 1);
+        arena.alloc(layout(32, 1));
+        arena.alloc(layout(32, 1));
+        assert_eq!(acquired.get(), 1);
+        arena.alloc(layout(32, 1));
+        assert_eq!((acquired.get(), arena.chunk_count()), (2, 2));
+
+        // Chunks left over from before a reset are reused.
+        arena.reset();
+        for _ in 0..3 {
+            arena.alloc(layout(32, 1));
+        }
+        assert_eq!(acquired.get(), 2);
+        arena.reset();
+        assert_eq!(arena.shrink_chunks_to(64), 64);
+        assert_eq!(released.get(), 1);
+        drop(arena);
+        assert_eq!(released.get(), 2);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn mmap_chunk_source_hands_out_whole_pages() {
+        let arena =
+            ChunkedArena::with_source(10, GrowthPolicy::Fixed(10), MmapChunkSource).unwrap();
+        let page = unsafe { sys::sysconf(sys::SC_PAGESIZE) } as usize;
+        assert_eq!(arena.capacity(), page);
+        let block = arena.alloc(layout(page + 1, 8));
+        unsafe { block.add(page).write(1) };
+        assert_eq!(arena.chunk_count(), 2);
+    }
+
+    #[test]
+    fn iter_bytes_chunked_covers_the_used_region() {
+        let arena = Arena::new(2048);
+        for i in 0..200u32 {
+            arena.alloc(i.wrapping_mul(2_654_435_761));
+        }
+        arena.alloc(7u8);
+        let windows: Vec<&[u8]> = arena.iter_bytes_chunked(256).collect();
+        let (last, full) = windows.split_last().unwrap();
+        assert!(full.iter().all(|window| window.len() == 256));
+        assert!(last.len() <= 256);
+        assert_eq!(windows.concat(), arena.snapshot().bytes);
+        assert_eq!(Arena::new(0).iter_bytes_chunked(8).count(), 0);
+    }
+
+    #[test]
+    fn chunked_iter_bytes_chunked_stops_at_chunk_ends() {
+        let arena = ChunkedArena::with_policy(100, GrowthPolicy::Fixed(100));
+        for _ in 0..5 {
+            unsafe { arena.alloc(layout(60, 1)).write_bytes(9, 60) };
+        }
+        let windows: Vec<&[u8]> = arena.iter_bytes_chunked(32).collect();
+        // Five chunks of 60 bytes, in windows of 32 and 28.
+        assert_eq!(windows.len(), 10);
+        assert!(windows.iter().all(|window| window.iter().all(|&b| b == 9)));
+        assert_eq!(
+            windows.iter().map(|window| window.len()).sum::<usize>(),
+            300
+        );
+    }
+
+    #[test]
+    fn stats_count_each_allocation_path() {
+        let chunked = ChunkedArena::with_policy(64, GrowthPolicy::Fixed(64));
+        for _ in 0..4 {
+            chunked.alloc(layout(16, 1));
+        }
+        let stats = chunked.stats();
+        assert_eq!(
+            (stats.fast_allocs, stats.fallback_allocs, stats.grow_events),
+            (4, 0, 0)
+        );
+        // The fifth spills into a new chunk, where it's bumped as usual.
+        chunked.alloc(layout(16, 1));
+        let stats = chunked.stats();
+        assert_eq!(
+            (
+                stats.alloc_count,
+                stats.fast_allocs,
+                stats.fallback_allocs,
+                stats.grow_events
+            ),
+            (5, 5, 1, 1)
+        );
+
+        let arena = Arena::new(64);
+        arena.enable_free_list();
+        let first = arena.try_alloc(layout(16, 1)).unwrap().as_ptr();
+        arena.try_alloc(layout(16, 1)).unwrap();
+        unsafe { arena.bump.dealloc(first, layout(16, 1)) };
+        arena.try_alloc(layout(16, 1)).unwrap();
+        assert!(!arena.alloc_fast(layout(16, 1)).is_null());
+        let stats = arena.stats();
+        assert_eq!(
+            (stats.alloc_count, stats.fast_allocs, stats.freelist_allocs),
+            (4, 3, 1)
+        );
+        arena.reset_stats();
+        assert_eq!(
+            (arena.stats().fast_allocs, arena.stats().freelist_allocs),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn set_usable_end_caps_the_heap() {
+        let arena = Arena::new(1024).align_base_to(8);
+        arena.alloc(1u64);
+        let next = arena.mark().position;
+        unsafe { arena.set_usable_end(next + 8) };
+        assert_eq!(arena.capacity(), 16);
+        assert!(arena.try_alloc(layout(8, 8)).is_ok());
+        assert!(arena.try_alloc(layout(1, 1)).is_err());
+
+        // Never below the cursor, nor past the end of the real heap.
+        unsafe { arena.set_usable_end(0) };
+        assert_eq!(arena.capacity(), 16);
+        unsafe { arena.set_usable_end(usize::MAX) };
+        assert_eq!(arena.capacity(), 1024);
+        arena.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn batch_bumps_the_cursor_once() {
+        let mut arena = Arena::new(1 << 16).align_base_to(8);
+        arena.enable_tracking(64);
+        let before = arena.alloc_count();
+        {
+            let batch = arena.batch(4000);
+            for _ in 0..1000 {
+                assert!(!batch.alloc(layout(3, 1)).is_null());
+            }
+            assert_eq!(arena.alloc_count() - before, 1);
+            assert_eq!(batch.remaining(), 1000);
+            // Too big for what's left, so it comes from the arena itself.
+            assert!(!batch.alloc(layout(2000, 8)).is_null());
+            assert_eq!(arena.alloc_count() - before, 2);
+        }
+        arena.check_invariants().unwrap();
+
+        // The unused tail goes back to the cursor when the batch ends.
+        let used = arena.bytes_used();
+        arena.batch(128).alloc(layout(3, 1));
+        assert_eq!(arena.bytes_used(), used + 3);
+        arena.check_invariants().unwrap();
+        assert!(!arena.batch(usize::MAX).alloc(layout(3, 1)).is_null());
+    }
+
+    #[test]
+    fn alloc_uninit_slice_can_be_written_then_assumed_init() {
+        let arena = Arena::new(64);
+        let slice = arena.alloc_uninit_slice(5).unwrap();
+        for (i, byte) in slice.iter_mut().enumerate() {
+            byte.write(b'a' + i as u8);
+        }
+        assert_eq!(unsafe { Arena::assume_init(slice) }, b"abcde");
+        assert!(arena.alloc_uninit_slice(100).is_none());
+    }
+
+    #[test]
+    #[cfg(not(feature = "single-threaded"))]
+    fn resets_between_rounds_of_threaded_allocation_never_alias() {
+        let mut arena = Arena::new(1 << 16);
+        arena.enable_tracking(1024);
+        arena.enable_free_list();
+        let (arena, rounds) = (&arena, &std::sync::Barrier::new(4));
+        std::thread::scope(|s| {
+            for thread in 0..4u8 {
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        let mut mine = Vec::new();
+                        for i in 0..50 {
+                            let block = layout(1 + (i * 7 + thread as usize) % 24, 1 << (i % 4));
+                            if let Ok(ptr) = arena.try_alloc(block) {
+                                unsafe { ptr.as_ptr().write_bytes(thread, block.size()) };
+                                mine.push((ptr.as_ptr(), block));
+                            }
+                        }
+                        for (i, &(ptr, block)) in mine.iter().enumerate() {
+                            let bytes = unsafe { core::slice::from_raw_parts(ptr, block.size()) };
+                            assert!(bytes.iter().all(|&b| b == thread));
+                            if i % 3 == 0 {
+                                unsafe { arena.bump.dealloc(ptr, block) };
+                            }
+                        }
+                        // Only reset once every thread is done allocating.
+                        rounds.wait();
+                        if thread == 0 {
+                            arena.check_invariants().unwrap();
+                            unsafe { arena.reset() };
+                        }
+                        rounds.wait();
+                    }
+                });
+            }
+        });
+        assert_eq!(arena.bytes_used(), 0);
+    }
+
+    #[test]
+    #[cfg(all(debug_assertions, not(feature = "single-threaded")))]
+    #[should_panic(expected = "reset while an allocation was in progress")]
+    fn reset_racing_an_allocation_panics_in_debug_builds() {
+        let arena = Arena::new(1 << 20);
+        let stop = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                while !stop.load(Ordering::Relaxed) {
+                    let _ = arena.try_alloc(layout(8, 8));
+                }
+            });
+            let resets = std::panic::catch_unwind(AssertUnwindSafe(|| loop {
+                unsafe { arena.reset() };
+            }));
+            stop.store(true, Ordering::Relaxed);
+            if let Err(panic) = resets {
+                std::panic::resume_unwind(panic);
+            }
+        });
+    }
+
+    #[test]
+    fn try_alloc_array_zeroed_clears_reused_memory() {
+        let arena = Arena::new(4096);
+        let dirty = arena.try_alloc(layout(800, 8)).unwrap().as_ptr();
+        unsafe {
+            dirty.write_bytes(0xff, 800);
+            arena.bump.dealloc(dirty, layout(800, 8));
+        }
+        let array = arena.try_alloc_array_zeroed::<u64>(100).unwrap();
+        let array = unsafe { array.as_ref() };
+        assert_eq!(array.len(), 100);
+        assert!(array.iter().all(|&x| x == 0));
+
+        // Too big for the heap, or for a `Layout` at all.
+        assert_eq!(
+            arena.try_alloc_array_zeroed::<u64>(1000).unwrap_err(),
+            AllocError::OutOfMemory
+        );
+        assert_eq!(
+            arena
+                .try_alloc_array_zeroed::<u64>(usize::MAX / 4)
+                .unwrap_err(),
+            AllocError::OutOfMemory
+        );
+        assert_eq!(
+            arena.try_alloc_array_zeroed::<()>(1000).unwrap().len(),
+            1000
+        );
+    }
+
+    #[test]
+    fn padding_for_reports_the_next_allocations_padding() {
+        let arena = Arena::new(4096).align_base_to(64);
+        assert_eq!(arena.padding_for(64), 0);
+        arena.alloc_packed(3);
+        assert_eq!(arena.padding_for(64), 61);
+        assert_eq!(arena.padding_for(4), 1);
+        assert_eq!(arena.padding_for(1), 0);
+        // Only a question: the cursor stays where it was.
+        assert_eq!(arena.bytes_used(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "record-ops")]
+    fn replaying_the_op_log_reproduces_the_arena() {
+        let mut arena = Arena::new(8192).align_base_to(64);
+        assert!(arena.op_log().is_empty());
+        arena.enable_op_log();
+        unsafe {
+            let first = arena.bump.alloc(layout(24, 8));
+            let second = arena.bump.alloc(layout(100, 16));
+            arena.bump.alloc(layout(7, 1));
+            arena.bump.dealloc(second, layout(100, 16));
+            let fourth = arena.bump.alloc(layout(64, 8));
+            arena.bump.realloc(fourth, layout(64, 8), 128);
+            assert!(arena.bump.alloc(layout(100_000, 8)).is_null());
+            arena.bump.dealloc(first, layout(24, 8));
+            arena.bump.alloc(layout(40, 32));
+
+            let mark = arena.mark();
+            arena.alloc_fast(layout(16, 8));
+            arena.alloc_in_range(layout(32, 8), 2048, 4096);
+            arena.rewind(mark);
+            arena.alloc_packed(3);
+            arena.alloc_with_offset_align(layout(48, 8), 8, 32);
+            let mark = arena.mark();
+            arena.alloc_fast(layout(200, 8));
+            arena.free_above(mark);
+        }
+        {
+            let batch = arena.batch(256);
+            batch.alloc(layout(10, 2));
+            let scope = arena.with_affinity("nodes");
+            scope.alloc(layout(24, 8));
+        }
+        drop(Reclaim::new_in([1u64; 4], &arena).unwrap());
+        let frame = arena.begin_generation();
+        arena.alloc_fast(layout(8, 8));
+        unsafe { arena.free_generation(frame) };
+
+        let log = arena.op_log().to_vec();
+        assert!(log
+            .iter()
+            .any(|op| matches!(op, RecordedOp::Alloc { offset: None, .. })));
+        let rewinds = log
+            .iter()
+            .filter(|op| matches!(op, RecordedOp::Rewind { .. }))
+            .count();
+        assert_eq!(rewinds, 3);
+        assert!(log.iter().any(|op| matches!(op, RecordedOp::Trim { .. })));
+        assert!(log.iter().any(|op| matches!(
+            op,
+            RecordedOp::AllocInRange {
+                offset: Some(_),
+                ..
+            }
+        )));
+        assert!(log
+            .iter()
+            .any(|op| matches!(op, RecordedOp::AllocWithOffsetAlign { .. })));
+
+        let copy = Arena::new(8192).align_base_to(64);
+        copy.enable_op_log();
+        unsafe { replay(&copy, &log) };
+        assert_eq!(copy.bytes_used(), arena.bytes_used());
+        assert_eq!(copy.stats(), arena.stats());
+        assert_eq!(copy.op_log(), &log[..]);
+        unsafe { arena.reset() };
+        assert_eq!(arena.op_log().last(), Some(&RecordedOp::Reset));
+    }
+
+    #[test]
+    fn align_base_to_gives_an_aligned_heap() {
+        let arena = Arena::new(3 * 4096).align_base_to(4096);
+        assert_eq!(arena.capacity(), 3 * 4096);
+        assert_eq!(arena.padding_for(4096), 0);
+        let block = arena.try_alloc(layout(100, 4096)).unwrap();
+        assert!((block.as_ptr() as usize).is_multiple_of(4096));
+        assert_eq!(arena.bytes_used(), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "in use")]
+    fn align_base_to_refuses_an_arena_in_use() {
+        let arena = Arena::new(100);
+        arena.alloc(1u8);
+        let _ = arena.align_base_to(64);
+    }
+
+    #[test]
+    fn align_base_to_drops_markers_and_frames_from_before() {
+        let mut arena = Arena::new(4096);
+        let frame = arena.begin_generation();
+        let marker = arena.mark();
+        let mut arena = arena.align_base_to(4096);
+        arena.alloc(1u64);
+        let used = arena.bytes_used();
+        assert!(!unsafe { arena.free_generation(frame) });
+        unsafe { arena.rewind(marker) };
+        assert_eq!(arena.bytes_used(), used);
+        arena.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn rewind_ignores_a_marker_below_the_heap() {
+        let arena = Arena::new(64).align_base_to(8);
+        arena.alloc(1u64);
+        let below = Marker {
+            position: arena.heap_range().0 - 1,
+            ..arena.mark()
+        };
+        unsafe { arena.rewind(below) };
+        assert_eq!(arena.bytes_used(), 8);
+        assert!(arena.allocations_above(below).is_empty());
+    }
+
+    #[test]
+    fn moving_realloc_keeps_the_alignment() {
+        let arena = Arena::new(8192);
+        unsafe {
+            let block = arena.bump.alloc(layout(40, 64));
+            for i in 0..40 {
+                block.add(i).write(i as u8);
+            }
+            // Something on top, so the block can't grow in place.
+            arena.bump.alloc(layout(3, 1));
+            let moved = arena.bump.realloc(block, layout(40, 64), 200);
+            assert_ne!(moved, block);
+            assert!((moved as usize).is_multiple_of(64));
+            assert!((0..40).all(|i| *moved.add(i) == i as u8));
+
+            arena.bump.alloc(layout(100, 1));
+            let again = arena.bump.realloc_align(moved, layout(200, 64), 400, 8);
+            assert_ne!(again, moved);
+            assert!((again as usize).is_multiple_of(64));
+            assert!((0..40).all(|i| *again.add(i) == i as u8));
+        }
+    }
+
+    #[test]
+    fn allocations_above_lists_what_a_rewind_would_free() {
+        let mut arena = Arena::new(4096);
+        arena.enable_tracking(16);
+        arena.try_alloc(layout(32, 8)).unwrap();
+        let marker = arena.mark();
+        assert!(arena.allocations_above(marker).is_empty());
+
+        let second = arena.try_alloc(layout(32, 8)).unwrap().as_ptr() as usize;
+        let third = arena.try_alloc(layout(1, 1)).unwrap().as_ptr() as usize;
+        let start = arena.heap_range().0;
+        assert_eq!(
+            arena.allocations_above(marker),
+            [
+                AllocInfo {
+                    offset: second - start,
+                    size: 32
+                },
+                AllocInfo {
+                    offset: third - start,
+                    size: 1
+                },
+            ]
+        );
+        unsafe { arena.rewind(marker) };
+        assert!(arena.allocations_above(marker).is_empty());
+        assert_eq!(arena.allocations().len(), 1);
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        feature = "strict-reset",
+        not(feature = "single-threaded")
+    ))]
+    #[test]
+    fn racing_allocs_after_a_strict_reset_get_accessible_memory() {
+        if in_child() {
+            let arena = MmapArena::new(4096).unwrap();
+            arena.set_strict_reset(true);
+            // Threads poll rather than sleep on the start of each round, so
+            // that on a multi-core machine they reach `alloc` together.
+            let (started, done) = (AtomicUsize::new(0), std::sync::Barrier::new(4));
+            std::thread::scope(|s| {
+                for thread in 0..4 {
+                    let (arena, started, done) = (&arena, &started, &done);
+                    s.spawn(move || {
+                        for round in 1..=2000 {
+                            if thread == 0 {
+                                unsafe { arena.reset() };
+                                started.store(round, Ordering::Release);
+                            }
+                            while started.load(Ordering::Acquire) != round {
+                                std::thread::yield_now();
+                            }
+                            unsafe { arena.alloc(layout(8, 8)).write(1) };
+                            done.wait();
+                        }
+                    });
+                }
+            });
+            return;
+        }
+        let name = "tests::racing_allocs_after_a_strict_reset_get_accessible_memory";
+        assert_eq!(signal_in_child(name), None);
+    }
+}
@@ -0,0 +1,13 @@
+// Checks that misconfigurations `main.rs` asserts against at compile time
+// really do fail to build. The expected output is rustc's own wording, which
+// shifts between releases, so this only runs when asked for, as in
+// `BUMP_UI_TESTS=1 cargo test`; after a toolchain upgrade, refresh the
+// `.stderr` files with `TRYBUILD=overwrite`.
+#[test]
+fn bad_heap_sizes_fail_to_compile() {
+    if std::env::var_os("BUMP_UI_TESTS").is_none() {
+        return;
+    }
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,7 @@
+#[path = "../../main.rs"]
+#[allow(dead_code)]
+mod bump;
+
+const _: () = bump::check_heap_size(1001);
+
+fn main() {}